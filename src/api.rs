@@ -4,18 +4,33 @@
 // You should have received a copy of the MIT License
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
-use async_std::sync::RwLock;
+use async_compatibility_layer::async_primitives::broadcast::BroadcastSender;
+use async_std::{sync::RwLock, task::spawn};
+use commit::{Commitment, Committable};
 use ethers::abi::Address;
-use futures::FutureExt;
+use futures::{future::ready, stream, FutureExt, Stream, StreamExt, TryFutureExt};
 use sequencer::Transaction;
 use sequencer::{Vm, VmTransaction};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::io;
 use std::sync::Arc;
 use surf_disco::{error::ClientError, Url};
-use tide_disco::{error::ServerError, Api, App};
+use tide_disco::{error::ServerError, Api, App, StatusCode};
 
+use crate::blocks::{BlockStore, ExecutedBlock};
+use crate::mempool::{Mempool, MempoolError};
+use crate::metrics::metrics;
+use crate::multisig::MultisigOp;
+use crate::rpc;
+use crate::state::{Amount, Nonce};
+use crate::status::{node_status, NodeStatus, StatusOptions};
 use crate::RollupVM;
-use crate::{state::State, transaction::SignedTransaction};
+use crate::{
+    error::RollupError,
+    state::State,
+    transaction::{SignedTransaction, Transaction as RollupTransaction},
+};
 
 #[derive(Clone, Debug)]
 
@@ -27,6 +42,166 @@ use crate::{state::State, transaction::SignedTransaction};
 pub struct APIOptions {
     pub api_port: u16,
     pub sequencer_url: Url,
+    /// Stream of states published by the executor, used to release transactions held in the
+    /// mempool once their predecessors have been applied.
+    pub executor_stream: Option<BroadcastSender<(u64, State)>>,
+    /// Blocks recorded by the executor, served by the block explorer endpoints.
+    pub block_store: Arc<RwLock<BlockStore>>,
+    /// The L1 configuration of the executor, used to report on the rollup's L1 state in `status`.
+    pub status_options: Option<StatusOptions>,
+}
+
+/// The outcome of applying a transaction to a snapshot of the current state.
+///
+/// The balances and nonce are those that would result from the transaction. If the transaction is
+/// rejected, they are unchanged and `error` explains why.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SimulationResult {
+    /// The recovered sender, if the signature is valid.
+    pub sender: Option<Address>,
+    pub sender_balance: Amount,
+    pub sender_nonce: Nonce,
+    pub destination: Address,
+    pub destination_balance: Amount,
+    pub error: Option<RollupError>,
+}
+
+/*
+Applies a signed transaction to a copy of the state, without modifying the state itself.
+
+ # Parameters
+ - `state`: The current rollup state.
+ - `transaction`: The signed transaction to simulate.
+
+ # Returns
+ - The `SimulationResult`, including any `RollupError` the transaction would raise.
+*/
+pub fn simulate_transaction(state: &State, transaction: &SignedTransaction) -> SimulationResult {
+    let mut snapshot = state.clone();
+    let error = snapshot.apply_transaction(transaction).err();
+    let sender = transaction.recover().ok();
+    let destination = transaction.transaction.destination;
+    SimulationResult {
+        sender,
+        sender_balance: sender.map_or(0, |sender| snapshot.get_balance(&sender)),
+        sender_nonce: sender.map_or(0, |sender| snapshot.get_nonce(&sender)),
+        destination,
+        destination_balance: snapshot.get_balance(&destination),
+        error,
+    }
+}
+
+/// The HTTP status with which a transaction rejected with `err` is reported.
+pub fn error_status(err: &RollupError) -> StatusCode {
+    match err {
+        RollupError::SignatureError | RollupError::InsufficientSignatures { .. } => {
+            StatusCode::Unauthorized
+        }
+        RollupError::UnknownAccount { .. } => StatusCode::NotFound,
+        RollupError::InsufficientBalance { .. } => StatusCode::PaymentRequired,
+        RollupError::InvalidNonce { .. } | RollupError::AccountExists { .. } => {
+            StatusCode::Conflict
+        }
+        RollupError::OutsideValidityWindow { .. }
+        | RollupError::Overflow { .. }
+        | RollupError::NotMultisig { .. } => StatusCode::UnprocessableEntity,
+        RollupError::InvalidEthereumTransaction { .. }
+        | RollupError::MalformedTransaction
+        | RollupError::InvalidMultisigOwners { .. }
+        | RollupError::WrongChainId { .. }
+        | RollupError::TooManyRecipients { .. } => StatusCode::BadRequest,
+    }
+}
+
+/// Converts a `RollupError` into a `ServerError`.
+///
+/// The status is given by `error_status`, and the message is the JSON serialization of the
+/// `RollupError`, so that clients can recover the structured error from the response body.
+fn rollup_error(err: RollupError) -> ServerError {
+    ServerError {
+        status: error_status(&err),
+        message: serde_json::to_string(&err).expect("Serialization should not fail"),
+    }
+}
+
+/// The maximum number of blocks returned by a single `GET /blocks` request.
+pub const MAX_BLOCK_RANGE: u64 = 100;
+
+/// The number of transactions returned by a `GET /history` request if no limit is given.
+pub const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+/// The maximum number of transactions returned by a single `GET /history` request.
+pub const MAX_HISTORY_LIMIT: usize = 100;
+
+/*
+Checks that a transaction could be applied on top of the current state, before it is submitted.
+
+Transactions that fail these checks would be dropped by the executor, wasting namespace space.
+Nonces ahead of the sender's next expected nonce are accepted, since they may become valid once
+earlier transactions are applied.
+
+ # Parameters
+ - `state`: The current rollup state.
+ - `transaction`: The signed transaction to validate.
+
+ # Returns
+ - `Ok(Address)` with the recovered sender if the transaction is valid.
+ - `Err(RollupError)` if the signature is invalid, an Ethereum transaction is signed for another
+   chain, the transaction has expired, the sender has no account, the signers do not meet the
+   threshold of a multisig sender, the nonce has already been used, the sender's balance does not
+   cover the total amount of its transfers or the owners set by a multisig operation are invalid.
+*/
+pub fn validate_transaction(
+    state: &State,
+    transaction: &SignedTransaction,
+) -> Result<Address, RollupError> {
+    let sender = transaction.recover()?;
+    transaction.check_chain_id(state.chain_id())?;
+    transaction.transaction.check_recipients()?;
+    let RollupTransaction {
+        nonce,
+        valid_after,
+        valid_until,
+        ..
+    } = transaction.transaction;
+
+    let height = state.block_height();
+    if transaction.transaction.is_expired_after(height) {
+        return Err(RollupError::OutsideValidityWindow {
+            height,
+            valid_after,
+            valid_until,
+        });
+    }
+
+    if !state.has_account(&sender) {
+        return Err(RollupError::UnknownAccount { address: sender });
+    }
+    state.authorize(sender, transaction)?;
+    if let Some(MultisigOp::Create(multisig) | MultisigOp::SetOwners(multisig)) =
+        &transaction.transaction.multisig_op
+    {
+        multisig.validate()?;
+    }
+
+    let prev_nonce = state.get_nonce(&sender);
+    if nonce <= prev_nonce {
+        return Err(RollupError::InvalidNonce {
+            address: sender,
+            expected: prev_nonce + 1,
+            actual: nonce,
+        });
+    }
+
+    if !transaction
+        .transaction
+        .total_amount()
+        .is_some_and(|total| total <= state.get_balance(&sender))
+    {
+        return Err(RollupError::InsufficientBalance { address: sender });
+    }
+
+    Ok(sender)
 }
 
 /*
@@ -57,6 +232,133 @@ async fn submit_transaction(
     Ok(())
 }
 
+/*
+Submits a validated transaction to the sequencer, unless its nonce is ahead of the sender's next
+expected nonce. Such transactions are held in the mempool, so they can't be sequenced ahead of
+their predecessors.
+
+ # Parameters
+ - `sequencer_url`: The sequencer API endpoint URL.
+ - `state`: The current rollup state.
+ - `mempool`: The pool of future-nonce transactions.
+ - `sender`: The recovered sender of the transaction.
+ - `transaction`: The signed transaction to submit.
+
+ # Errors
+ - `UnprocessableEntity` if the nonce is too far ahead to be held, or `ServiceUnavailable` if the
+   mempool is full.
+*/
+pub(crate) async fn submit_or_hold_transaction(
+    sequencer_url: Url,
+    state: &State,
+    mempool: &RwLock<Mempool>,
+    sender: Address,
+    transaction: SignedTransaction,
+) -> Result<(), ServerError> {
+    if transaction.transaction.nonce > state.get_nonce(&sender) + 1 {
+        mempool
+            .write()
+            .await
+            .insert(sender, transaction, state)
+            .map_err(|err| ServerError {
+                status: match err {
+                    MempoolError::NonceTooFarAhead { .. } => StatusCode::UnprocessableEntity,
+                    MempoolError::Full => StatusCode::ServiceUnavailable,
+                },
+                message: err.to_string(),
+            })?;
+        return Ok(());
+    }
+    submit_transaction(sequencer_url, transaction, &state.vm).await
+}
+
+/*
+Forwards transactions held in the mempool to the sequencer as the executor applies their predecessors.
+
+ # Parameters
+ - `sequencer_url`: The sequencer API endpoint URL.
+ - `executor_stream`: The stream of states published by the executor after each block.
+ - `mempool`: The pool of future-nonce transactions shared with the API.
+*/
+async fn release_pending_transactions(
+    sequencer_url: Url,
+    executor_stream: BroadcastSender<(u64, State)>,
+    mempool: Arc<RwLock<Mempool>>,
+) {
+    let mut recv = executor_stream.handle_async().await;
+    while let Ok((block_height, state)) = recv.recv_async().await {
+        let ready = mempool.write().await.take_ready(&state);
+        for transaction in ready {
+            let nonce = transaction.transaction.nonce;
+            if let Err(err) =
+                submit_transaction(sequencer_url.clone(), transaction, &state.vm).await
+            {
+                tracing::error!(
+                    "Failed to release pending transaction {nonce} after block {block_height}: {err}"
+                );
+            }
+        }
+    }
+}
+
+/// A summary of a block executed by the rollup, published on `stream/blocks`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BlockSummary {
+    /// The HotShot height of the block.
+    pub height: u64,
+    /// The state commitment after the block was executed.
+    pub commitment: Commitment<State>,
+    /// The number of transactions from the block that were applied.
+    pub transaction_count: u64,
+}
+
+/// The balance and nonce of an account, published on `stream/account/:address` whenever they change.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AccountUpdate {
+    /// The HotShot height of the block which last changed the account.
+    pub height: u64,
+    pub address: Address,
+    pub balance: Amount,
+    pub nonce: Nonce,
+}
+
+/*
+Subscribes to the states published by the executor after each block.
+
+ # Parameters
+ - `executor_stream`: The stream of states published by the executor, if configured.
+ - `state`: The shared rollup state.
+
+ # Returns
+ - The current state, which precedes every state yielded by the stream, and the stream itself.
+ - `Err(ServerError)` if the API was started without an executor stream.
+*/
+async fn subscribe_executor(
+    executor_stream: Option<BroadcastSender<(u64, State)>>,
+    state: &RwLock<State>,
+) -> Result<(State, impl Stream<Item = (u64, State)>), ServerError> {
+    let executor_stream = executor_stream.ok_or_else(|| ServerError {
+        status: tide_disco::StatusCode::ServiceUnavailable,
+        message: "Streams are not available, the API is not connected to an executor.".into(),
+    })?;
+    // The executor publishes new states while holding the write lock, so holding the read lock
+    // while subscribing ensures that no state is missed between the snapshot and the stream.
+    let state = state.read().await;
+    let recv = executor_stream.handle_async().await;
+    let states = stream::unfold(recv, |mut recv| async move {
+        recv.recv_async().await.ok().map(|update| (update, recv))
+    });
+    Ok((state.clone(), states))
+}
+
+/// Takes a snapshot of the local status of the node, holding the state and block store locks only
+/// for the duration of the snapshot.
+async fn local_status(state: &RwLock<State>, block_store: &RwLock<BlockStore>) -> NodeStatus {
+    let state = state.read().await;
+    let block_store = block_store.read().await;
+    NodeStatus::local(&state, &block_store)
+}
+
 /*
 Serves an API for interacting with a rollup system, providing transaction submission,
  balance checking, and nonce retrieval functionalities.
@@ -68,9 +370,28 @@ Serves an API for interacting with a rollup system, providing transaction submis
  # Behavior
  - Initializes the API using configuration from a `TOML` file.
  - Defines the following endpoints:
-   - `POST /submit`: Submits a signed transaction to the sequencer.
+   - `POST /submit`: Validates a signed transaction and submits it to the sequencer, or holds it
+     in the mempool if its nonce is ahead of the sender's next expected nonce. Validation and the
+     mempool are bypassed with `POST /submit/true`.
    - `GET /balance`: Retrieves the balance for a specified Ethereum address.
    - `GET /nonce`: Retrieves the nonce for a specified Ethereum address.
+   - `POST /simulate`: Applies a signed transaction to a snapshot of the current state and reports
+     the outcome, without committing anything.
+   - `GET /pending`: Retrieves the transactions held in the mempool for a specified Ethereum address.
+   - `GET /block`: Retrieves an executed block by HotShot height, with the outcome of each transaction.
+   - `GET /blocks`: Retrieves a range of executed blocks.
+   - `GET /history`: Retrieves a page of the transactions sent or received by a specified Ethereum
+     address, newest first.
+   - `GET /status`: Reports execution progress, L1 verification progress and connectivity to the
+     sequencer and L1. `GET /status/live` succeeds whenever the API is up, while `GET /status/ready`
+     only succeeds once the node can reach its dependencies and has caught up with HotShot.
+   - `METRICS /metrics`: Exports Prometheus metrics for the executor, prover and API.
+   - `SOCKET /stream/blocks`: Streams a summary of each block executed by the rollup.
+   - `SOCKET /stream/account`: Streams the balance and nonce of a specified Ethereum address
+     whenever they change.
+ - Serves an Ethereum JSON-RPC facade at `POST /eth/rpc`, see the `rpc` module.
+ - If an executor stream is configured, releases held transactions in nonce order as the executor
+   applies their predecessors.
  - Maps common errors (e.g., invalid addresses, malformed transactions) to appropriate HTTP error responses.
  - Starts serving the API at the specified `api_port`.
 
@@ -85,23 +406,57 @@ pub async fn serve(options: &APIOptions, state: Arc<RwLock<State>>) -> io::Resul
     let APIOptions {
         api_port,
         sequencer_url,
-        ..
+        executor_stream,
+        block_store,
+        status_options,
     } = options.clone();
+    let mempool = Arc::new(RwLock::new(Mempool::default()));
+    if let Some(executor_stream) = executor_stream.clone() {
+        spawn(release_pending_transactions(
+            sequencer_url.clone(),
+            executor_stream,
+            mempool.clone(),
+        ));
+    }
     let mut app = App::<StateType, ServerError>::with_state(state);
     let toml = toml::from_str::<toml::Value>(include_str!("api.toml"))
         .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
     let mut api = Api::<StateType, ServerError>::new(toml).map_err(error_mapper)?;
 
+    let submit_mempool = mempool.clone();
+    let submit_url = sequencer_url.clone();
     api.post("submit",  move|req, state| {
-        let url = sequencer_url.clone();
+        let url = submit_url.clone();
+        let mempool = submit_mempool.clone();
         async move {
+            let _timer = metrics().api_request("submit");
             let transaction = req
                 .body_auto::<SignedTransaction>().
             map_err(|_| ServerError {
                 status: tide_disco::StatusCode::BadRequest,
                 message: "Malformed transaction. Ensure that the transaction is a JSON serialized SignedTransaction".into()
             })?;
-            submit_transaction(url, transaction, &state.vm).await
+            // Advanced clients may opt out of validation and forward the transaction as is
+            if req.opt_boolean_param("skip_validation")?.unwrap_or(false) {
+                return submit_transaction(url, transaction, &state.vm).await;
+            }
+            let sender = validate_transaction(state, &transaction).map_err(rollup_error)?;
+            submit_or_hold_transaction(url, state, &mempool, sender, transaction).await
+        }
+        .boxed()
+    })
+    .map_err(error_mapper)?;
+
+    api.post("simulate", |req, state| {
+        async move {
+            let _timer = metrics().api_request("simulate");
+            let transaction = req
+                .body_auto::<SignedTransaction>().
+            map_err(|_| ServerError {
+                status: tide_disco::StatusCode::BadRequest,
+                message: "Malformed transaction. Ensure that the transaction is a JSON serialized SignedTransaction".into()
+            })?;
+            Ok(simulate_transaction(state, &transaction))
         }
         .boxed()
     })
@@ -109,6 +464,7 @@ pub async fn serve(options: &APIOptions, state: Arc<RwLock<State>>) -> io::Resul
 
     api.get("balance", |req, state| {
         async move {
+            let _timer = metrics().api_request("balance");
             let address_str = req.string_param("address")?;
             let address = address_str.parse::<Address>().
             map_err(|_| ServerError {
@@ -124,6 +480,7 @@ pub async fn serve(options: &APIOptions, state: Arc<RwLock<State>>) -> io::Resul
 
     api.get("nonce", |req, state| {
         async move {
+            let _timer = metrics().api_request("nonce");
             let address_str = req.string_param("address")?;
             let address = address_str.parse::<Address>().
             map_err(|_| ServerError {
@@ -137,16 +494,236 @@ pub async fn serve(options: &APIOptions, state: Arc<RwLock<State>>) -> io::Resul
     })
     .map_err(error_mapper)?;
 
+    api.get("multisig", |req, state| {
+        async move {
+            let _timer = metrics().api_request("multisig");
+            let address_str = req.string_param("address")?;
+            let address = address_str.parse::<Address>().
+            map_err(|_| ServerError {
+                status: tide_disco::StatusCode::BadRequest,
+                message: "Malformed address. Ensure that the address is valid hex encoded Ethereum address.".into()
+            })?;
+            Ok(state.get_multisig(&address).cloned())
+        }
+        .boxed()
+    })
+    .map_err(error_mapper)?;
+
+    api.get("supply", |_req, state| {
+        async move {
+            let _timer = metrics().api_request("supply");
+            Ok(state.total_supply())
+        }
+        .boxed()
+    })
+    .map_err(error_mapper)?;
+
+    let pending_mempool = mempool.clone();
+    api.get("pending", move |req, _state| {
+        let mempool = pending_mempool.clone();
+        async move {
+            let _timer = metrics().api_request("pending");
+            let address_str = req.string_param("address")?;
+            let address = address_str.parse::<Address>().
+            map_err(|_| ServerError {
+                status: tide_disco::StatusCode::BadRequest,
+                message: "Malformed address. Ensure that the address is valid hex encoded Ethereum address.".into()
+            })?;
+            let pending = mempool.read().await.pending(&address);
+            Ok(pending)
+        }
+        .boxed()
+    })
+    .map_err(error_mapper)?;
+
+    let get_block_store = block_store.clone();
+    api.get("block", move |req, _state| {
+        let block_store = get_block_store.clone();
+        async move {
+            let _timer = metrics().api_request("block");
+            let height = req.integer_param("height")?;
+            let block = block_store.read().await.get(height).cloned();
+            block.ok_or_else(|| ServerError {
+                status: tide_disco::StatusCode::NotFound,
+                message: format!("Block {height} has not been executed by the rollup."),
+            })
+        }
+        .boxed()
+    })
+    .map_err(error_mapper)?;
+
+    let blocks_block_store = block_store.clone();
+    api.get("blocks", move |req, _state| {
+        let block_store = blocks_block_store.clone();
+        async move {
+            let _timer = metrics().api_request("blocks");
+            let from: u64 = req.integer_param("from")?;
+            let to: u64 = req.integer_param("to")?;
+            if to < from || to - from >= MAX_BLOCK_RANGE {
+                return Err(ServerError {
+                    status: tide_disco::StatusCode::BadRequest,
+                    message: format!(
+                        "Invalid block range. Ensure that from <= to and that at most {MAX_BLOCK_RANGE} blocks are requested."
+                    ),
+                });
+            }
+            let blocks: Vec<ExecutedBlock> = block_store.read().await.range(from..=to);
+            Ok(blocks)
+        }
+        .boxed()
+    })
+    .map_err(error_mapper)?;
+
+    let history_block_store = block_store.clone();
+    api.get("history", move |req, _state| {
+        let block_store = history_block_store.clone();
+        async move {
+            let _timer = metrics().api_request("history");
+            let address_str = req.string_param("address")?;
+            let address = address_str.parse::<Address>().
+            map_err(|_| ServerError {
+                status: tide_disco::StatusCode::BadRequest,
+                message: "Malformed address. Ensure that the address is valid hex encoded Ethereum address.".into()
+            })?;
+            let cursor = req.opt_integer_param("cursor")?;
+            let limit = req
+                .opt_integer_param("limit")?
+                .unwrap_or(DEFAULT_HISTORY_LIMIT)
+                .clamp(1, MAX_HISTORY_LIMIT);
+            let page = block_store.read().await.history(&address, cursor, limit);
+            Ok(page)
+        }
+        .boxed()
+    })
+    .map_err(error_mapper)?;
+
+    let status_block_store = block_store.clone();
+    let status_sequencer_url = sequencer_url.clone();
+    let status_route_options = status_options.clone();
+    // The status routes check dependencies over the network, so they take a snapshot of the local
+    // status and release the state and block store locks before doing any I/O, rather than block
+    // the executor for the duration of the checks.
+    api.at("status", move |_req, state| {
+        let block_store = status_block_store.clone();
+        let sequencer_url = status_sequencer_url.clone();
+        let status_options = status_route_options.clone();
+        async move {
+            let _timer = metrics().api_request("status");
+            let local = local_status(state, &block_store).await;
+            let status = node_status(status_options.as_ref(), &sequencer_url, local).await;
+            Ok(status)
+        }
+        .boxed()
+    })
+    .map_err(error_mapper)?;
+
+    api.get("liveness", |_req, _state| {
+        async move {
+            let _timer = metrics().api_request("liveness");
+            Ok(true)
+        }
+        .boxed()
+    })
+    .map_err(error_mapper)?;
+
+    let readiness_block_store = block_store.clone();
+    let readiness_sequencer_url = sequencer_url.clone();
+    api.at("readiness", move |_req, state| {
+        let block_store = readiness_block_store.clone();
+        let sequencer_url = readiness_sequencer_url.clone();
+        let status_options = status_options.clone();
+        async move {
+            let _timer = metrics().api_request("readiness");
+            let local = local_status(state, &block_store).await;
+            let status = node_status(status_options.as_ref(), &sequencer_url, local).await;
+            if !status.is_ready() {
+                return Err(ServerError {
+                    status: tide_disco::StatusCode::ServiceUnavailable,
+                    message: serde_json::to_string(&status).expect("Serialization should not fail"),
+                });
+            }
+            Ok(status)
+        }
+        .boxed()
+    })
+    .map_err(error_mapper)?;
+
+    api.metrics("metrics", |_req, _state| {
+        async move { Ok(Cow::Borrowed(&metrics().registry)) }.boxed()
+    })
+    .map_err(error_mapper)?;
+
+    let blocks_stream = executor_stream.clone();
+    api.stream("stream_blocks", move |_req, state| {
+        let executor_stream = blocks_stream.clone();
+        async move {
+            let (state, states) = subscribe_executor(executor_stream, state).await?;
+            let mut prev_count = state.transaction_count();
+            Ok(states.map(move |(height, state)| {
+                let count = state.transaction_count();
+                let summary = BlockSummary {
+                    height,
+                    commitment: state.commit(),
+                    transaction_count: count - prev_count,
+                };
+                prev_count = count;
+                Ok(summary)
+            }))
+        }
+        .try_flatten_stream()
+        .boxed()
+    })
+    .map_err(error_mapper)?;
+
+    api.stream("stream_account", move |req, state| {
+        let executor_stream = executor_stream.clone();
+        async move {
+            let address_str = req.string_param("address")?;
+            let address = address_str.parse::<Address>().
+            map_err(|_| ServerError {
+                status: tide_disco::StatusCode::BadRequest,
+                message: "Malformed address. Ensure that the address is valid hex encoded Ethereum address.".into()
+            })?;
+            let (state, states) = subscribe_executor(executor_stream, state).await?;
+            let account_update = move |height: u64, state: &State| AccountUpdate {
+                height,
+                address,
+                balance: state.get_balance(&address),
+                nonce: state.get_nonce(&address),
+            };
+            // Start with the current account, then only send changes
+            let current = account_update(state.block_height(), &state);
+            let mut prev = current.clone();
+            let updates = states.filter_map(move |(height, state)| {
+                let update = account_update(height, &state);
+                let changed = (update.balance, update.nonce) != (prev.balance, prev.nonce);
+                if changed {
+                    prev = update.clone();
+                }
+                ready(changed.then_some(update))
+            });
+            Ok(stream::once(ready(current)).chain(updates).map(Ok))
+        }
+        .try_flatten_stream()
+        .boxed()
+    })
+    .map_err(error_mapper)?;
+
     app.register_module("rollup", api)
         .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    app.register_module("eth", rpc::define_api(sequencer_url, mempool, block_store)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
     app.serve(format!("0.0.0.0:{}", api_port)).await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::transaction::Transaction;
-    use async_std::task::spawn;
+    use crate::client::RollupApiClient;
+    use crate::multisig::Multisig;
+    use crate::transaction::{Recipient, Transaction};
+    use async_compatibility_layer::async_primitives::broadcast;
+    use async_std::{future::timeout, task::spawn};
     use ethers::signers::{LocalWallet, Signer};
     use futures::future::ready;
     use portpicker::pick_unused_port;
@@ -159,7 +736,7 @@ mod tests {
         testing::wait_for_decide_on_handle,
         Transaction as SeqTransaction,
     };
-    use surf_disco::Client;
+    use std::time::Duration;
     use tempfile::TempDir;
 
     const GENESIS_BALANCE: u64 = 9999;
@@ -176,10 +753,13 @@ mod tests {
         )));
         let port = pick_unused_port().expect("No ports free");
         let api_url: Url = format!("http://localhost:{port}").parse().unwrap();
-        let client: Client<ServerError> = Client::new(api_url.clone());
+        let client = RollupApiClient::new(api_url.clone());
         let options = APIOptions {
             api_port: port,
             sequencer_url: api_url,
+            executor_stream: None,
+            block_store: Default::default(),
+            status_options: None,
         };
 
         spawn(async move { serve(&options, state).await });
@@ -187,13 +767,404 @@ mod tests {
         client.connect(None).await;
 
         // Fetch genesis block balance
-        let balance = client
-            .get::<u64>(&format!("rollup/balance/{:?}", genesis_address))
+        let balance = client.balance(genesis_address).await.unwrap();
+
+        assert_eq!(balance, GENESIS_BALANCE);
+    }
+
+    #[async_std::test]
+    async fn multisig_test() {
+        let mut rng = rand::thread_rng();
+        let genesis_wallet = LocalWallet::new(&mut rng);
+        let owner = LocalWallet::new(&mut rng).address();
+        let vm = RollupVM::new(1.into());
+        let genesis_address = genesis_wallet.address();
+        let mut state = State::from_initial_balances([(genesis_address, GENESIS_BALANCE)], vm);
+
+        // Create a multisig account
+        let multisig = Multisig {
+            owners: vec![genesis_address, owner],
+            threshold: 2,
+        };
+        let multisig_address = Multisig::address(genesis_address, 1);
+        let transaction = Transaction {
+            amount: 0,
+            destination: multisig_address,
+            nonce: 1,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: Some(MultisigOp::Create(multisig.clone())),
+        };
+        state
+            .apply_transaction(&SignedTransaction::new(transaction, &genesis_wallet).await)
+            .unwrap();
+
+        let state = Arc::new(RwLock::new(state));
+        let port = pick_unused_port().expect("No ports free");
+        let api_url: Url = format!("http://localhost:{port}").parse().unwrap();
+        let client = RollupApiClient::new(api_url.clone());
+        let options = APIOptions {
+            api_port: port,
+            sequencer_url: api_url.clone(),
+            executor_stream: None,
+            block_store: Default::default(),
+            status_options: None,
+        };
+
+        spawn(async move { serve(&options, state).await });
+
+        client.connect(None).await;
+
+        // Fetch the owners of the multisig account
+        assert_eq!(
+            client.multisig(multisig_address).await.unwrap(),
+            Some(multisig)
+        );
+
+        // Ordinary and unknown accounts have no owners
+        assert_eq!(client.multisig(genesis_address).await.unwrap(), None);
+        assert_eq!(client.multisig(owner).await.unwrap(), None);
+
+        // Malformed addresses are rejected
+        let err = surf_disco::Client::<ServerError>::new(api_url)
+            .get::<Option<Multisig>>("rollup/multisig/not-an-address")
             .send()
             .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::BadRequest);
+    }
+
+    #[async_std::test]
+    async fn supply_test() {
+        let mut rng = rand::thread_rng();
+        let genesis_wallet = LocalWallet::new(&mut rng);
+        let receiver = LocalWallet::new(&mut rng).address();
+        let vm = RollupVM::new(1.into());
+        let genesis_address = genesis_wallet.address();
+        let mut state =
+            State::from_initial_balances([(genesis_address, GENESIS_BALANCE), (receiver, 1)], vm);
+
+        // Transfers move tokens without changing the supply
+        let transaction = Transaction {
+            amount: 100,
+            destination: receiver,
+            nonce: 1,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
+        };
+        state
+            .apply_transaction(&SignedTransaction::new(transaction, &genesis_wallet).await)
             .unwrap();
 
-        assert_eq!(balance, GENESIS_BALANCE);
+        let state = Arc::new(RwLock::new(state));
+        let port = pick_unused_port().expect("No ports free");
+        let api_url: Url = format!("http://localhost:{port}").parse().unwrap();
+        let client = RollupApiClient::new(api_url.clone());
+        let options = APIOptions {
+            api_port: port,
+            sequencer_url: api_url,
+            executor_stream: None,
+            block_store: Default::default(),
+            status_options: None,
+        };
+
+        spawn(async move { serve(&options, state).await });
+
+        client.connect(None).await;
+
+        // The total supply is the sum of the genesis balances
+        assert_eq!(client.supply().await.unwrap(), GENESIS_BALANCE + 1);
+        assert_eq!(client.balance(receiver).await.unwrap(), 101);
+    }
+
+    #[async_std::test]
+    async fn stream_test() {
+        let mut rng = rand::thread_rng();
+        let genesis_wallet = LocalWallet::new(&mut rng);
+        let receiver = LocalWallet::new(&mut rng).address();
+        let vm = RollupVM::new(1.into());
+        let genesis_address = genesis_wallet.address();
+        let genesis = State::from_initial_balances([(genesis_address, GENESIS_BALANCE)], vm);
+        let (executor_send, _) = broadcast::channel();
+        let port = pick_unused_port().expect("No ports free");
+        let api_url: Url = format!("http://localhost:{port}").parse().unwrap();
+        let client = RollupApiClient::new(api_url.clone());
+        let options = APIOptions {
+            api_port: port,
+            sequencer_url: api_url,
+            executor_stream: Some(executor_send.clone()),
+            block_store: Default::default(),
+            status_options: None,
+        };
+
+        let state = Arc::new(RwLock::new(genesis.clone()));
+        spawn(async move { serve(&options, state).await });
+
+        client.connect(None).await;
+
+        // The states the executor would publish: a transfer, an empty block, another transfer
+        let mut states = vec![];
+        let mut state = genesis.clone();
+        for (height, nonce) in [(1, Some(1)), (2, None), (3, Some(2))] {
+            if let Some(nonce) = nonce {
+                let transaction = Transaction {
+                    amount: 10,
+                    destination: receiver,
+                    nonce,
+                    valid_after: None,
+                    valid_until: None,
+                    recipients: vec![],
+                    multisig: None,
+                    multisig_op: None,
+                };
+                state
+                    .apply_transaction(&SignedTransaction::new(transaction, &genesis_wallet).await)
+                    .unwrap();
+            }
+            states.push((height, state.clone()));
+        }
+
+        let mut blocks = Box::pin(client.stream_blocks().await.unwrap());
+        let mut updates = Box::pin(client.stream_account(genesis_address).await.unwrap());
+
+        // The account stream starts with the current account
+        assert_eq!(
+            updates.next().await.unwrap().unwrap(),
+            AccountUpdate {
+                height: 0,
+                address: genesis_address,
+                balance: GENESIS_BALANCE,
+                nonce: 0,
+            }
+        );
+
+        // Only states published after the server has subscribed are streamed, so publish the first
+        // state until it arrives. Repeats do not change the account, and are skipped below.
+        let first = loop {
+            executor_send.send_async(states[0].clone()).await.ok();
+            if let Ok(summary) = timeout(Duration::from_millis(100), blocks.next()).await {
+                break summary.unwrap().unwrap();
+            }
+        };
+        assert_eq!(
+            first,
+            BlockSummary {
+                height: 1,
+                commitment: states[0].1.commit(),
+                transaction_count: 1,
+            }
+        );
+        for update in &states[1..] {
+            executor_send.send_async(update.clone()).await.unwrap();
+        }
+        let summaries: Vec<BlockSummary> = blocks
+            .map(Result::unwrap)
+            .skip_while(|summary| ready(summary.height == 1))
+            .take(2)
+            .collect()
+            .await;
+        assert_eq!(
+            summaries,
+            [
+                BlockSummary {
+                    height: 2,
+                    commitment: states[1].1.commit(),
+                    transaction_count: 0,
+                },
+                BlockSummary {
+                    height: 3,
+                    commitment: states[2].1.commit(),
+                    transaction_count: 1,
+                },
+            ]
+        );
+
+        // Account updates are only sent by blocks which change the account
+        for (height, nonce) in [(1, 1), (3, 2)] {
+            assert_eq!(
+                updates.next().await.unwrap().unwrap(),
+                AccountUpdate {
+                    height,
+                    address: genesis_address,
+                    balance: GENESIS_BALANCE - 10 * nonce,
+                    nonce,
+                }
+            );
+        }
+    }
+
+    #[async_std::test]
+    async fn simulate_test() {
+        let mut rng = rand::thread_rng();
+        let genesis_wallet = LocalWallet::new(&mut rng);
+        let receiver = LocalWallet::new(&mut rng).address();
+        let vm = RollupVM::new(1.into());
+        let genesis_address = genesis_wallet.address();
+        let state = Arc::new(RwLock::new(State::from_initial_balances(
+            [(genesis_address, GENESIS_BALANCE)],
+            vm,
+        )));
+        let port = pick_unused_port().expect("No ports free");
+        let api_url: Url = format!("http://localhost:{port}").parse().unwrap();
+        let client = RollupApiClient::new(api_url.clone());
+        let options = APIOptions {
+            api_port: port,
+            sequencer_url: api_url,
+            executor_stream: None,
+            block_store: Default::default(),
+            status_options: None,
+        };
+
+        spawn(async move { serve(&options, state).await });
+
+        client.connect(None).await;
+
+        // Simulate a valid transfer
+        let mut transaction = Transaction {
+            amount: 100,
+            destination: receiver,
+            nonce: 1,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
+        };
+        let signed_transaction = SignedTransaction::new(transaction.clone(), &genesis_wallet).await;
+        let result = client.simulate(&signed_transaction).await.unwrap();
+        assert_eq!(
+            result,
+            SimulationResult {
+                sender: Some(genesis_address),
+                sender_balance: GENESIS_BALANCE - 100,
+                sender_nonce: 1,
+                destination: receiver,
+                destination_balance: 100,
+                error: None,
+            }
+        );
+
+        // Simulate an overspend
+        transaction.amount = GENESIS_BALANCE + 1;
+        let signed_transaction = SignedTransaction::new(transaction, &genesis_wallet).await;
+        let result = client.simulate(&signed_transaction).await.unwrap();
+        assert_eq!(
+            result.error,
+            Some(RollupError::InsufficientBalance {
+                address: genesis_address
+            })
+        );
+
+        // Nothing was committed
+        let balance = client.balance(receiver).await.unwrap();
+        assert_eq!(balance, 0);
+    }
+
+    #[async_std::test]
+    async fn submit_validation_test() {
+        let mut rng = rand::thread_rng();
+        let genesis_wallet = LocalWallet::new(&mut rng);
+        let stranger = LocalWallet::new(&mut rng);
+        let vm = RollupVM::new(1.into());
+        let genesis_address = genesis_wallet.address();
+        let state = Arc::new(RwLock::new(State::from_initial_balances(
+            [(genesis_address, GENESIS_BALANCE)],
+            vm,
+        )));
+        let port = pick_unused_port().expect("No ports free");
+        let api_url: Url = format!("http://localhost:{port}").parse().unwrap();
+        let client = RollupApiClient::new(api_url.clone());
+        let options = APIOptions {
+            api_port: port,
+            sequencer_url: api_url,
+            executor_stream: None,
+            block_store: Default::default(),
+            status_options: None,
+        };
+
+        spawn(async move { serve(&options, state).await });
+
+        client.connect(None).await;
+
+        let submit = |signed_transaction: SignedTransaction| {
+            let client = client.clone();
+            async move {
+                let err = client
+                    .submit(&signed_transaction)
+                    .await
+                    .expect_err("Invalid transaction should be rejected");
+                err.rollup_error()
+                    .cloned()
+                    .expect("Rejection should carry a RollupError")
+            }
+        };
+
+        // Overspend
+        let mut transaction = Transaction {
+            amount: GENESIS_BALANCE + 1,
+            destination: stranger.address(),
+            nonce: 1,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
+        };
+        let signed_transaction = SignedTransaction::new(transaction.clone(), &genesis_wallet).await;
+        assert_eq!(
+            submit(signed_transaction).await,
+            RollupError::InsufficientBalance {
+                address: genesis_address
+            }
+        );
+
+        // Stale nonce
+        transaction.amount = 1;
+        transaction.nonce = 0;
+        let signed_transaction = SignedTransaction::new(transaction.clone(), &genesis_wallet).await;
+        assert_eq!(
+            submit(signed_transaction).await,
+            RollupError::InvalidNonce {
+                address: genesis_address,
+                expected: 1,
+                actual: 0,
+            }
+        );
+
+        // Unknown sender
+        transaction.nonce = 1;
+        let signed_transaction = SignedTransaction::new(transaction, &stranger).await;
+        assert_eq!(
+            submit(signed_transaction).await,
+            RollupError::UnknownAccount {
+                address: stranger.address()
+            }
+        );
+
+        // Batch whose total, but no single amount, exceeds the balance
+        let recipients = vec![
+            Recipient {
+                destination: stranger.address(),
+                amount: GENESIS_BALANCE,
+            },
+            Recipient {
+                destination: stranger.address(),
+                amount: 1,
+            },
+        ];
+        let transaction = Transaction::batch(recipients, 1).unwrap();
+        let signed_transaction = SignedTransaction::new(transaction, &genesis_wallet).await;
+        assert_eq!(
+            submit(signed_transaction).await,
+            RollupError::InsufficientBalance {
+                address: genesis_address
+            }
+        );
     }
 
     #[async_std::test]
@@ -242,6 +1213,9 @@ mod tests {
         let options = APIOptions {
             api_port,
             sequencer_url,
+            executor_stream: None,
+            block_store: Default::default(),
+            status_options: None,
         };
         spawn(async move { serve(&options, state).await });
 
@@ -250,20 +1224,19 @@ mod tests {
             amount: 100,
             destination: genesis_address,
             nonce: 1,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
         };
         let signed_transaction = SignedTransaction::new(transaction, &genesis_wallet).await;
 
         // Submit the transaction
         let api_url = format!("http://localhost:{api_port}").parse().unwrap();
-        let api_client: Client<ServerError> = Client::new(api_url);
+        let api_client = RollupApiClient::new(api_url);
         api_client.connect(None).await;
-        api_client
-            .post::<()>("rollup/submit")
-            .body_json(&signed_transaction)
-            .unwrap()
-            .send()
-            .await
-            .unwrap();
+        api_client.submit(&signed_transaction).await.unwrap();
 
         // Wait for a Decide event containing transaction matching the one we sent
         let raw_tx = signed_transaction.encode();
@@ -0,0 +1,694 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! A load generator for capacity planning.
+//!
+//! The benchmark funds a set of accounts derived from a seed, then sends transfers between them at
+//! a target rate, tracking nonces locally. Each transfer is matched against the blocks published on
+//! the executor's `rollup/stream/blocks` stream to measure submit-to-execution latency, and against
+//! the verification status of those blocks to measure submit-to-L1-verification latency.
+//!
+//! Failed requests to the rollup API do not end the run. They are counted as request errors in the
+//! report, and the request is retried or, for a benchmark account which cannot be set up, the
+//! account is left out.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Debug, Display, Formatter},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_std::{
+    sync::Mutex,
+    task::{sleep, spawn},
+};
+use clap::Parser;
+use ethers::{
+    signers::{LocalWallet, Signer},
+    types::Address,
+};
+use example_l2::{
+    blocks::ExecutedBlock,
+    client::{ApiError, RollupApiClient},
+    seed::SeedIdentity,
+    state::{Amount, Nonce},
+    transaction::{SignedTransaction, Transaction},
+};
+use futures::StreamExt;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use sequencer::{Transaction as SeqTransaction, VmTransaction};
+use snafu::Snafu;
+use surf_disco::Client;
+use tide_disco::{error::ServerError, Url};
+
+/// How often balances and block verification are polled.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Parser, Clone, Debug)]
+struct Options {
+    /// Url of the Rollup client
+    #[clap(short, long, default_value = "http://localhost:8084")]
+    rollup_url: Url,
+
+    /// Submit transfers directly to the `submit/submit` endpoint of the sequencer at this URL,
+    /// instead of through `rollup/submit`.
+    #[clap(long)]
+    sequencer_url: Option<Url>,
+
+    /// VM ID of the rollup, used when submitting directly to the sequencer.
+    #[clap(long, default_value = "1")]
+    vm_id: u64,
+
+    /// Number of accounts sending transfers.
+    #[clap(long, default_value = "10")]
+    accounts: usize,
+
+    /// Seed from which the benchmark accounts are derived.
+    #[clap(long, default_value = "1000")]
+    seed: u64,
+
+    /// Preseeded identity which funds the benchmark accounts.
+    #[clap(long, value_enum, default_value = "Alice")]
+    funder: SeedIdentity,
+
+    /// Balance each benchmark account is funded with. Each transfer sends 1 token.
+    #[clap(long, default_value = "100")]
+    funding: Amount,
+
+    /// Target rate of transfers, per second.
+    #[clap(long, default_value = "10")]
+    rate: f64,
+
+    /// How long to send transfers for, in seconds.
+    #[clap(long, default_value = "60")]
+    duration: u64,
+
+    /// How long to wait for outstanding transfers to be executed and verified after the last
+    /// submission, in seconds.
+    #[clap(long, default_value = "120")]
+    drain_timeout: u64,
+}
+
+/// An error which ends the benchmark before it can send transfers.
+#[derive(Snafu)]
+enum BenchError {
+    #[snafu(display("{message}"))]
+    InvalidOptions { message: String },
+    #[snafu(display("Could not connect to the Rollup API at {url}. Ensure that the API is running and that the supplied URL is correct."))]
+    Unreachable { url: Url },
+    #[snafu(display("{message}: {source}"))]
+    Api { message: String, source: ApiError },
+    #[snafu(display("None of the benchmark accounts could be set up"))]
+    NoAccounts,
+}
+
+// `main` reports a returned error with `Debug`, so show the message instead of the structure.
+impl Debug for BenchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Options {
+    fn check(&self) -> Result<(), BenchError> {
+        let invalid = |message: &str| {
+            Err(BenchError::InvalidOptions {
+                message: message.into(),
+            })
+        };
+        if self.accounts == 0 {
+            return invalid("At least one account is required");
+        }
+        if !(self.rate.is_finite() && self.rate > 0.0) {
+            return invalid("The rate must be positive");
+        }
+        Ok(())
+    }
+}
+
+/// Timing of the transfers sent by the benchmark.
+#[derive(Debug, Default)]
+struct Recorder {
+    /// Submission time of transfers which have not been executed yet.
+    submitted: HashMap<(Address, Nonce), Instant>,
+    /// Submission times of executed transfers whose block has not been verified yet, by height.
+    unverified: BTreeMap<u64, Vec<Instant>>,
+    execution_latencies: Vec<Duration>,
+    verification_latencies: Vec<Duration>,
+    submit_errors: usize,
+    /// Failed queries and subscriptions to the rollup API.
+    request_errors: usize,
+    rejected: usize,
+    last_execution: Option<Instant>,
+}
+
+impl Recorder {
+    fn outstanding(&self) -> usize {
+        self.submitted.len() + self.unverified.values().map(Vec::len).sum::<usize>()
+    }
+
+    /// Counts a failed request to the rollup API.
+    fn request_error(&mut self, request: &str, err: ApiError) {
+        eprintln!("Error {request}: {err}");
+        self.request_errors += 1;
+    }
+
+    /// Aggregates the results of a run which started at `start` and sent `sent` transfers in
+    /// `submission_time`.
+    fn report(&mut self, start: Instant, sent: usize, submission_time: Duration) -> Report {
+        let executed = self.execution_latencies.len();
+        Report {
+            sent,
+            submission_time,
+            executed,
+            rejected: self.rejected,
+            submit_errors: self.submit_errors,
+            request_errors: self.request_errors,
+            outstanding: self.submitted.len(),
+            execution_throughput: self
+                .last_execution
+                .map(|last_execution| executed as f64 / (last_execution - start).as_secs_f64()),
+            execution_latency: Latencies::new(&mut self.execution_latencies),
+            verification_latency: Latencies::new(&mut self.verification_latencies),
+        }
+    }
+}
+
+/// The `p`th percentile, by nearest rank, of a non-empty list of sorted samples.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let index = ((sorted.len() - 1) as f64 * p / 100.0).round() as usize;
+    sorted[index]
+}
+
+/// Percentiles of a set of latencies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Latencies {
+    p50: Duration,
+    p90: Duration,
+    p99: Duration,
+    max: Duration,
+}
+
+impl Latencies {
+    /// Sorts the samples and computes their percentiles, or `None` if there are no samples.
+    fn new(samples: &mut [Duration]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort();
+        Some(Self {
+            p50: percentile(samples, 50.0),
+            p90: percentile(samples, 90.0),
+            p99: percentile(samples, 99.0),
+            max: percentile(samples, 100.0),
+        })
+    }
+}
+
+/// The results of a benchmark run.
+#[derive(Debug)]
+struct Report {
+    sent: usize,
+    submission_time: Duration,
+    executed: usize,
+    rejected: usize,
+    submit_errors: usize,
+    request_errors: usize,
+    /// Transfers which have not been executed.
+    outstanding: usize,
+    /// Executed transfers per second, up to the last execution.
+    execution_throughput: Option<f64>,
+    execution_latency: Option<Latencies>,
+    verification_latency: Option<Latencies>,
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Submitted: {} transfers in {:.1}s",
+            self.sent,
+            self.submission_time.as_secs_f64()
+        )?;
+        writeln!(
+            f,
+            "Submission rate: {:.2} transfers/s",
+            self.sent as f64 / self.submission_time.as_secs_f64()
+        )?;
+        writeln!(
+            f,
+            "Executed: {}, rejected: {}, failed to submit: {}, outstanding: {}",
+            self.executed, self.rejected, self.submit_errors, self.outstanding
+        )?;
+        writeln!(f, "Request errors: {}", self.request_errors)?;
+        if let Some(throughput) = self.execution_throughput {
+            writeln!(f, "Execution throughput: {throughput:.2} transfers/s")?;
+        }
+        for (name, latencies) in [
+            ("Execution", self.execution_latency),
+            ("L1 verification", self.verification_latency),
+        ] {
+            match latencies {
+                Some(latencies) => writeln!(
+                    f,
+                    "{name} latency (s): p50 {:.2}, p90 {:.2}, p99 {:.2}, max {:.2}",
+                    latencies.p50.as_secs_f64(),
+                    latencies.p90.as_secs_f64(),
+                    latencies.p99.as_secs_f64(),
+                    latencies.max.as_secs_f64(),
+                )?,
+                None => writeln!(f, "{name} latency: no samples")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sends transfers either through the rollup API or directly to the sequencer.
+#[derive(Clone, Debug)]
+enum Submitter {
+    Rollup(RollupApiClient),
+    Sequencer {
+        client: Client<ServerError>,
+        vm_id: u64,
+    },
+}
+
+impl Submitter {
+    async fn submit(&self, transaction: &SignedTransaction) -> Result<(), String> {
+        match self {
+            Self::Rollup(client) => client
+                .submit(transaction)
+                .await
+                .map_err(|err| err.to_string()),
+            Self::Sequencer { client, vm_id } => {
+                let txn = SeqTransaction::new((*vm_id).into(), transaction.encode().to_vec());
+                client
+                    .post::<()>("submit/submit")
+                    .body_json(&txn)
+                    .map_err(|err| err.to_string())?
+                    .send()
+                    .await
+                    .map_err(|err| err.to_string())
+            }
+        }
+    }
+}
+
+fn transfer(destination: Address, amount: Amount, nonce: Nonce) -> Transaction {
+    Transaction {
+        amount,
+        destination,
+        nonce,
+        valid_after: None,
+        valid_until: None,
+        recipients: vec![],
+        multisig: None,
+        multisig_op: None,
+    }
+}
+
+/// Funds every benchmark account up to `funding` from the funder, and waits until the transfers
+/// have been applied. Returns the accounts which were funded, leaving out those which could not be.
+async fn fund_accounts(
+    client: &RollupApiClient,
+    funder: &LocalWallet,
+    accounts: Vec<LocalWallet>,
+    funding: Amount,
+    recorder: &Mutex<Recorder>,
+) -> Result<Vec<LocalWallet>, BenchError> {
+    let mut nonce = client
+        .nonce(funder.address())
+        .await
+        .map_err(|source| BenchError::Api {
+            message: "Error fetching the funder's nonce".into(),
+            source,
+        })?;
+    let mut funded = vec![];
+    for account in accounts {
+        let balance = match client.balance(account.address()).await {
+            Ok(balance) => balance,
+            Err(err) => {
+                let request = format!("fetching the balance of {:?}", account.address());
+                recorder.lock().await.request_error(&request, err);
+                continue;
+            }
+        };
+        if balance < funding {
+            let transaction = transfer(account.address(), funding - balance, nonce + 1);
+            let signed_transaction = SignedTransaction::new(transaction, funder).await;
+            if let Err(err) = client.submit(&signed_transaction).await {
+                let request = format!("funding {:?}", account.address());
+                recorder.lock().await.request_error(&request, err);
+                continue;
+            }
+            nonce += 1;
+        }
+        funded.push(account);
+    }
+    if funded.is_empty() {
+        return Err(BenchError::NoAccounts);
+    }
+
+    println!("Funding {} accounts", funded.len());
+    for account in &funded {
+        loop {
+            match client.balance(account.address()).await {
+                Ok(balance) if balance >= funding => break,
+                Ok(_) => {}
+                Err(err) => {
+                    let request = format!("fetching the balance of {:?}", account.address());
+                    recorder.lock().await.request_error(&request, err);
+                }
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+    Ok(funded)
+}
+
+/// Matches executed blocks against the submitted transfers.
+///
+/// Transfers in a block which cannot be fetched are never matched, and are reported as outstanding.
+async fn record_executions(client: RollupApiClient, recorder: Arc<Mutex<Recorder>>) {
+    loop {
+        let mut blocks = match client.stream_blocks().await {
+            Ok(blocks) => Box::pin(blocks),
+            Err(err) => {
+                recorder
+                    .lock()
+                    .await
+                    .request_error("subscribing to executed blocks", err);
+                sleep(POLL_INTERVAL).await;
+                continue;
+            }
+        };
+        while let Some(summary) = blocks.next().await {
+            let summary = match summary {
+                Ok(summary) => summary,
+                Err(err) => {
+                    recorder
+                        .lock()
+                        .await
+                        .request_error("receiving an executed block", err);
+                    continue;
+                }
+            };
+            let now = Instant::now();
+            match client.block(summary.height).await {
+                Ok(Some(block)) => record_block(&recorder, block, now).await,
+                Ok(None) => {}
+                Err(err) => {
+                    let request = format!("fetching executed block {}", summary.height);
+                    recorder.lock().await.request_error(&request, err);
+                }
+            }
+        }
+        // The stream ended, e.g. because the connection dropped, so subscribe again
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Matches the transfers of an executed block, received at `now`, against the submitted ones.
+async fn record_block(recorder: &Mutex<Recorder>, block: ExecutedBlock, now: Instant) {
+    let mut recorder = recorder.lock().await;
+    for executed in block.transactions {
+        let (Some(sender), Some(transaction)) = (executed.sender, executed.transaction) else {
+            continue;
+        };
+        let key = (sender, transaction.transaction.nonce);
+        let Some(submitted) = recorder.submitted.remove(&key) else {
+            continue;
+        };
+        if executed.error.is_some() {
+            recorder.rejected += 1;
+            continue;
+        }
+        recorder.execution_latencies.push(now - submitted);
+        recorder.last_execution = Some(now);
+        recorder
+            .unverified
+            .entry(block.height)
+            .or_default()
+            .push(submitted);
+    }
+}
+
+/// Polls the executed blocks which contain benchmark transfers, oldest first, until they are
+/// verified on L1.
+async fn record_verifications(client: RollupApiClient, recorder: Arc<Mutex<Recorder>>) {
+    loop {
+        sleep(POLL_INTERVAL).await;
+        let heights: Vec<u64> = recorder.lock().await.unverified.keys().copied().collect();
+        for height in heights {
+            let verified = match client.block(height).await {
+                Ok(block) => block.is_some_and(|block| block.verified),
+                Err(err) => {
+                    let request = format!("fetching executed block {height}");
+                    recorder.lock().await.request_error(&request, err);
+                    // Retry on the next poll
+                    break;
+                }
+            };
+            // Blocks are verified in order
+            if !verified {
+                break;
+            }
+            let now = Instant::now();
+            let mut recorder = recorder.lock().await;
+            for submitted in recorder.unverified.remove(&height).unwrap_or_default() {
+                recorder.verification_latencies.push(now - submitted);
+            }
+        }
+    }
+}
+
+#[async_std::main]
+async fn main() -> Result<(), BenchError> {
+    let opt = Options::parse();
+    opt.check()?;
+
+    let client = RollupApiClient::new(opt.rollup_url.clone());
+    if !client.connect(Some(Duration::from_secs(2))).await {
+        return Err(BenchError::Unreachable {
+            url: opt.rollup_url,
+        });
+    }
+    let submitter = match &opt.sequencer_url {
+        Some(url) => {
+            let client = Client::<ServerError>::new(url.clone());
+            client.connect(Some(Duration::from_secs(2))).await;
+            Submitter::Sequencer {
+                client,
+                vm_id: opt.vm_id,
+            }
+        }
+        None => Submitter::Rollup(client.clone()),
+    };
+
+    let funder = LocalWallet::new(&mut ChaChaRng::seed_from_u64(opt.funder as u64));
+    let mut rng = ChaChaRng::seed_from_u64(opt.seed);
+    let accounts: Vec<LocalWallet> = (0..opt.accounts)
+        .map(|_| LocalWallet::new(&mut rng))
+        .collect();
+    let recorder = Arc::new(Mutex::new(Recorder::default()));
+    let funded = fund_accounts(&client, &funder, accounts, opt.funding, &recorder).await?;
+
+    // Track nonces locally, so that transfers can be sent without waiting for earlier ones
+    let mut accounts = vec![];
+    let mut nonces = vec![];
+    for account in funded {
+        match client.nonce(account.address()).await {
+            Ok(nonce) => {
+                accounts.push(account);
+                nonces.push(nonce);
+            }
+            Err(err) => {
+                let request = format!("fetching the nonce of {:?}", account.address());
+                recorder.lock().await.request_error(&request, err);
+            }
+        }
+    }
+    if accounts.is_empty() {
+        return Err(BenchError::NoAccounts);
+    }
+
+    spawn(record_executions(client.clone(), recorder.clone()));
+    spawn(record_verifications(client.clone(), recorder.clone()));
+
+    println!(
+        "Sending transfers at {} per second for {}s",
+        opt.rate, opt.duration
+    );
+    let interval = Duration::from_secs_f64(1.0 / opt.rate);
+    let start = Instant::now();
+    let end = start + Duration::from_secs(opt.duration);
+    let mut sent = 0usize;
+    loop {
+        let due = start + interval.mul_f64(sent as f64);
+        if due >= end {
+            break;
+        }
+        sleep(due.saturating_duration_since(Instant::now())).await;
+
+        // Send in a round robin, each account paying the next one
+        let index = sent % accounts.len();
+        let destination = accounts[(index + 1) % accounts.len()].address();
+        nonces[index] += 1;
+        let transaction = transfer(destination, 1, nonces[index]);
+        let signed_transaction = SignedTransaction::new(transaction, &accounts[index]).await;
+        let key = (accounts[index].address(), nonces[index]);
+        let submitter = submitter.clone();
+        let recorder = recorder.clone();
+        spawn(async move {
+            recorder.lock().await.submitted.insert(key, Instant::now());
+            if let Err(err) = submitter.submit(&signed_transaction).await {
+                eprintln!("Failed to submit transfer {key:?}: {err}");
+                let mut recorder = recorder.lock().await;
+                recorder.submitted.remove(&key);
+                recorder.submit_errors += 1;
+            }
+        });
+        sent += 1;
+    }
+    let submission_time = start.elapsed();
+
+    // Wait for outstanding transfers to be executed and verified
+    let drain_end = Instant::now() + Duration::from_secs(opt.drain_timeout);
+    while Instant::now() < drain_end && recorder.lock().await.outstanding() > 0 {
+        sleep(POLL_INTERVAL).await;
+    }
+
+    let report = recorder.lock().await.report(start, sent, submission_time);
+    print!("{report}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: &[u64]) -> Vec<Duration> {
+        secs.iter().copied().map(Duration::from_secs).collect()
+    }
+
+    #[test]
+    fn test_percentile() {
+        let samples = secs(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+        assert_eq!(percentile(&samples, 0.0), Duration::from_secs(1));
+        assert_eq!(percentile(&samples, 50.0), Duration::from_secs(6));
+        assert_eq!(percentile(&samples, 90.0), Duration::from_secs(10));
+        assert_eq!(percentile(&samples, 100.0), Duration::from_secs(11));
+        // Ranks are rounded to the nearest sample
+        assert_eq!(percentile(&samples, 44.0), Duration::from_secs(5));
+        assert_eq!(percentile(&samples, 46.0), Duration::from_secs(6));
+
+        let single = secs(&[3]);
+        for p in [0.0, 50.0, 99.0, 100.0] {
+            assert_eq!(percentile(&single, p), Duration::from_secs(3));
+        }
+    }
+
+    #[test]
+    fn test_latencies() {
+        assert_eq!(Latencies::new(&mut []), None);
+
+        // Samples are sorted before taking percentiles
+        let mut samples = secs(&[5, 1, 4, 2, 3]);
+        assert_eq!(
+            Latencies::new(&mut samples),
+            Some(Latencies {
+                p50: Duration::from_secs(3),
+                p90: Duration::from_secs(5),
+                p99: Duration::from_secs(5),
+                max: Duration::from_secs(5),
+            })
+        );
+        assert_eq!(samples, secs(&[1, 2, 3, 4, 5]));
+    }
+
+    #[test]
+    fn test_report() {
+        let start = Instant::now();
+        let address = Address::repeat_byte(1);
+        let mut recorder = Recorder {
+            submitted: HashMap::from([((address, 5), start)]),
+            unverified: BTreeMap::from([(3, vec![start])]),
+            execution_latencies: secs(&[2, 1, 3, 4]),
+            verification_latencies: secs(&[10]),
+            submit_errors: 1,
+            request_errors: 2,
+            rejected: 3,
+            last_execution: Some(start + Duration::from_secs(2)),
+        };
+        assert_eq!(recorder.outstanding(), 2);
+
+        let report = recorder.report(start, 9, Duration::from_secs(3));
+        assert_eq!(report.sent, 9);
+        assert_eq!(report.executed, 4);
+        assert_eq!(report.rejected, 3);
+        assert_eq!(report.submit_errors, 1);
+        assert_eq!(report.request_errors, 2);
+        assert_eq!(report.outstanding, 1);
+        assert_eq!(report.execution_throughput, Some(2.0));
+        assert_eq!(
+            report.execution_latency,
+            Some(Latencies {
+                p50: Duration::from_secs(3),
+                p90: Duration::from_secs(4),
+                p99: Duration::from_secs(4),
+                max: Duration::from_secs(4),
+            })
+        );
+        assert_eq!(
+            report.verification_latency.unwrap().max,
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            report.to_string(),
+            "Submitted: 9 transfers in 3.0s\n\
+             Submission rate: 3.00 transfers/s\n\
+             Executed: 4, rejected: 3, failed to submit: 1, outstanding: 1\n\
+             Request errors: 2\n\
+             Execution throughput: 2.00 transfers/s\n\
+             Execution latency (s): p50 3.00, p90 4.00, p99 4.00, max 4.00\n\
+             L1 verification latency (s): p50 10.00, p90 10.00, p99 10.00, max 10.00\n"
+        );
+    }
+
+    #[test]
+    fn test_empty_report() {
+        let start = Instant::now();
+        let report = Recorder::default().report(start, 0, Duration::from_secs(1));
+        assert_eq!(report.executed, 0);
+        assert_eq!(report.execution_throughput, None);
+        assert_eq!(report.execution_latency, None);
+        assert_eq!(report.verification_latency, None);
+        let report = report.to_string();
+        assert!(!report.contains("throughput"), "{report}");
+        assert!(report.contains("Execution latency: no samples"), "{report}");
+        assert!(
+            report.contains("L1 verification latency: no samples"),
+            "{report}"
+        );
+    }
+
+    #[test]
+    fn test_check_options() {
+        let opt = Options::parse_from(["bench"]);
+        opt.check().unwrap();
+        Options::parse_from(["bench", "--accounts", "0"])
+            .check()
+            .unwrap_err();
+        for rate in ["0", "-1", "inf", "NaN"] {
+            Options::parse_from(["bench".to_string(), format!("--rate={rate}")])
+                .check()
+                .unwrap_err();
+        }
+    }
+}
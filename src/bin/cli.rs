@@ -4,25 +4,43 @@
 // You should have received a copy of the MIT License
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
-use std::time::Duration;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
-use clap::{Args, Parser, Subcommand};
+use async_std::task::sleep;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use ethers::{
     prelude::k256::ecdsa::SigningKey,
-    signers::{LocalWallet, Signer, Wallet},
+    signers::{coins_bip39::English, LocalWallet, MnemonicBuilder, Signer, Wallet, WalletError},
     types::Address,
 };
 use example_l2::{
+    client::{ApiError, RollupApiClient},
+    error::RollupError,
+    multisig::{Multisig, MultisigOp},
     seed::SeedIdentity,
     state::{Amount, Nonce},
-    transaction::{SignedTransaction, Transaction},
+    transaction::{Recipient, SignedTransaction, Transaction, MAX_RECIPIENTS},
 };
 use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
-use surf_disco::Client;
-use tide_disco::{error::ServerError, Url};
+use serde::Serialize;
+use snafu::{ResultExt, Snafu};
+use tide_disco::Url;
+
+/// Environment variable holding the password of keystores. If it is not set, the password is
+/// prompted for.
+const KEYSTORE_PASSWORD_ENV: &str = "ESPRESSO_DEMO_KEYSTORE_PASSWORD";
 
-type RollupClient = Client<ServerError>;
+/// File in the wallet directory mapping each alias to the address of its keystore, so that
+/// addresses can be looked up without decrypting the keystores.
+const WALLET_INDEX_FILE: &str = "addresses.json";
 
 #[derive(Parser, Clone, Debug)]
 pub struct Options {
@@ -30,96 +48,1722 @@ pub struct Options {
     #[clap(short, long, default_value = "http://localhost:8084")]
     pub rollup_url: Url,
 
+    /// Directory of the keystores managed by `wallet`. Defaults to
+    /// `$XDG_CONFIG_HOME/example-rollup/wallets`, or `~/.config/example-rollup/wallets`.
+    #[clap(long, env = "ESPRESSO_DEMO_WALLET_DIR")]
+    pub wallet_dir: Option<PathBuf>,
+
+    /// Print results and errors as human readable text, or as JSON for scripts.
+    #[clap(long, value_enum, default_value = "text", global = true)]
+    pub output: OutputFormat,
+
     #[command(subcommand)]
     pub command: ExampleRollupCommand,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
 #[derive(Subcommand, Clone, Debug)]
 pub enum ExampleRollupCommand {
     Transfer(Transfer),
+    /// Pay several recipients, read from a CSV file, in a single transaction.
+    TransferBatch(TransferBatch),
     CheckBalance(CheckBalance),
+    /// Sign a transfer offline, without contacting the rollup.
+    Sign(Sign),
+    /// Add the signature of a further owner to a multisig transaction signed with `sign`.
+    Cosign(Cosign),
+    /// Create a multisig account, from which transactions must be signed by several owners.
+    CreateMultisig(CreateMultisig),
+    /// Submit a transaction signed with `sign`.
+    Broadcast(SignedFile),
+    /// Show the fields and the recovered sender of a transaction signed with `sign`.
+    Decode(SignedFile),
+    /// Check the signature of a transaction signed with `sign`.
+    Verify(Verify),
+    /// Manage encrypted keystores with named aliases.
+    #[command(subcommand)]
+    Wallet(WalletCommand),
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum WalletCommand {
+    /// Generate a new key and store it under an alias.
+    New { alias: String },
+    /// Store an existing key under an alias.
+    Import {
+        alias: String,
+        #[command(flatten)]
+        key: SenderKey,
+        /// Account index when importing from `--mnemonic`.
+        #[clap(long, default_value = "0", requires = "mnemonic")]
+        index: u32,
+    },
+    /// List the stored aliases and their addresses.
+    List,
+    /// Print the address stored under an alias.
+    ExportAddress { alias: String },
+    /// Delete the keystore stored under an alias.
+    Remove { alias: String },
+}
+
+/// An account given on the command line: one of the demo's preseeded identities, a hex encoded
+/// Ethereum address or the alias of a wallet.
+#[derive(Clone, Debug)]
+pub enum Account {
+    Identity(SeedIdentity),
+    Address(Address),
+    Alias(String),
+}
+
+impl FromStr for Account {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(identity) = <SeedIdentity as ValueEnum>::from_str(s, false) {
+            return Ok(Self::Identity(identity));
+        }
+        if let Ok(address) = s.parse::<Address>() {
+            return Ok(Self::Address(address));
+        }
+        Ok(Self::Alias(s.to_string()))
+    }
+}
+
+impl Account {
+    fn address(&self, wallets: &WalletDir) -> Result<Address, CliError> {
+        match self {
+            Self::Identity(identity) => Ok(get_wallet_from_identity(identity).address()),
+            Self::Address(address) => Ok(*address),
+            Self::Alias(alias) => wallets.address(alias),
+        }
+    }
+}
+
+/// The key used to sign a transaction. Exactly one source must be given.
+#[derive(Args, Clone, Debug)]
+#[group(required = true, multiple = false)]
+pub struct SenderKey {
+    /// Sign as one of the demo's preseeded identities, or with the keystore of a wallet alias.
+    #[clap(long)]
+    pub from: Option<Account>,
+    /// Sign with a hex encoded private key.
+    #[clap(long, env = "ESPRESSO_DEMO_PRIVATE_KEY", hide_env_values = true)]
+    pub private_key: Option<String>,
+    /// Sign with a key derived from a BIP-39 mnemonic, at the account index given by `--index`.
+    #[clap(long, env = "ESPRESSO_DEMO_MNEMONIC", hide_env_values = true)]
+    pub mnemonic: Option<String>,
+    /// Sign with the key in an encrypted JSON keystore file. The password is read from
+    /// ESPRESSO_DEMO_KEYSTORE_PASSWORD, or prompted for if it is not set.
+    #[clap(long)]
+    pub keystore: Option<PathBuf>,
 }
 
 #[derive(Args, Clone, Debug)]
 pub struct Transfer {
-    pub sender: SeedIdentity,
-    pub receiver: SeedIdentity,
+    #[command(flatten)]
+    pub sender: SenderKey,
+    /// Account index of the sender when signing with `--mnemonic`.
+    #[clap(long, default_value = "0", requires = "mnemonic")]
+    pub index: u32,
+    /// A preseeded identity, a hex encoded Ethereum address or a wallet alias.
+    pub receiver: Account,
     pub amount: Amount,
+    /// Last HotShot block height at which the transfer may be applied.
+    #[clap(long)]
+    pub valid_until: Option<u64>,
+    /// Wait until the transfer has been executed by the rollup, or with `--wait=verified` until
+    /// its block has also been verified by the rollup contract on L1.
+    #[clap(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "executed"
+    )]
+    pub wait: Option<WaitFor>,
+    /// How long to wait with `--wait`, in seconds.
+    #[clap(long, default_value = "120", requires = "wait")]
+    pub timeout: u64,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct TransferBatch {
+    #[command(flatten)]
+    pub sender: SenderKey,
+    /// Account index of the sender when signing with `--mnemonic`.
+    #[clap(long, default_value = "0", requires = "mnemonic")]
+    pub index: u32,
+    /// CSV file with a `receiver,amount` row for each recipient, where the receiver is a
+    /// preseeded identity, a hex encoded Ethereum address or a wallet alias. An optional
+    /// `receiver,amount` header row, empty lines and lines starting with `#` are ignored.
+    pub recipients: PathBuf,
+    /// Last HotShot block height at which the transfer may be applied.
+    #[clap(long)]
+    pub valid_until: Option<u64>,
+    /// Wait until the transfer has been executed by the rollup, or with `--wait=verified` until
+    /// its block has also been verified by the rollup contract on L1.
+    #[clap(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "executed"
+    )]
+    pub wait: Option<WaitFor>,
+    /// How long to wait with `--wait`, in seconds.
+    #[clap(long, default_value = "120", requires = "wait")]
+    pub timeout: u64,
+}
+
+/// The milestone `transfer --wait` waits for.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaitFor {
+    Executed,
+    Verified,
+}
+
+/// How often `transfer --wait` polls the rollup API.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Args, Clone, Debug)]
+pub struct Sign {
+    #[command(flatten)]
+    pub sender: SenderKey,
+    /// Account index of the sender when signing with `--mnemonic`.
+    #[clap(long, default_value = "0", requires = "mnemonic")]
+    pub index: u32,
+    /// A preseeded identity, a hex encoded Ethereum address or a wallet alias.
+    pub receiver: Account,
+    pub amount: Amount,
+    /// Nonce of the transfer, which must be one more than the sender's nonce when it is applied.
+    #[clap(long)]
+    pub nonce: Nonce,
+    /// Send from a multisig account instead of the signer's account. Further owners of the
+    /// account add their signatures with `cosign`.
+    #[clap(long)]
+    pub multisig: Option<Account>,
+    /// Replace the owners of the `--multisig` account with a comma separated list of accounts.
+    #[clap(long, value_delimiter = ',', requires_all = ["multisig", "threshold"])]
+    pub set_owners: Option<Vec<Account>>,
+    /// The number of owners which must sign transactions of the account, with `--set-owners`.
+    #[clap(long, requires = "set_owners")]
+    pub threshold: Option<u32>,
+    /// HotShot block height after which the transfer may be applied.
+    #[clap(long)]
+    pub valid_after: Option<u64>,
+    /// Last HotShot block height at which the transfer may be applied.
+    #[clap(long)]
+    pub valid_until: Option<u64>,
+    /// File to write the signed transaction to. Defaults to stdout.
+    #[clap(long)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct SignedFile {
+    /// File containing a JSON serialized signed transaction, or `-` for stdin.
+    pub file: PathBuf,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct Cosign {
+    #[command(flatten)]
+    pub signed: SignedFile,
+    #[command(flatten)]
+    pub sender: SenderKey,
+    /// Account index of the owner when signing with `--mnemonic`.
+    #[clap(long, default_value = "0", requires = "mnemonic")]
+    pub index: u32,
+    /// File to write the signed transaction to. Defaults to stdout.
+    #[clap(long)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct CreateMultisig {
+    #[command(flatten)]
+    pub sender: SenderKey,
+    /// Account index of the creator when signing with `--mnemonic`.
+    #[clap(long, default_value = "0", requires = "mnemonic")]
+    pub index: u32,
+    /// Comma separated owners of the account: preseeded identities, hex encoded Ethereum
+    /// addresses or wallet aliases.
+    #[clap(long, value_delimiter = ',', required = true)]
+    pub owners: Vec<Account>,
+    /// The number of owners which must sign transactions of the account.
+    #[clap(long)]
+    pub threshold: u32,
+    /// Tokens to transfer from the creator to the new account.
+    #[clap(long, default_value = "0")]
+    pub amount: Amount,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct Verify {
+    #[command(flatten)]
+    pub signed: SignedFile,
+    /// The account expected to have signed the transaction.
+    #[clap(long)]
+    pub signer: Option<Account>,
 }
 
 #[derive(Args, Clone, Debug)]
 pub struct CheckBalance {
-    pub identity: SeedIdentity,
+    /// A preseeded identity, a hex encoded Ethereum address or a wallet alias.
+    pub account: Account,
 }
 
 fn get_wallet_from_identity(identity: &SeedIdentity) -> Wallet<SigningKey> {
     LocalWallet::new(&mut ChaChaRng::seed_from_u64(*identity as u64))
 }
 
-async fn transfer(transfer: &Transfer, client: &RollupClient) {
-    let sender = get_wallet_from_identity(&transfer.sender);
-    let receiver = get_wallet_from_identity(&transfer.receiver);
+/// An error reported by the CLI. Each class of error exits with a different status code.
+#[derive(Debug, Snafu)]
+pub enum CliError {
+    #[snafu(display("{message}"))]
+    InvalidInput { message: String },
+    #[snafu(display("{message}: {source}"))]
+    Io { message: String, source: io::Error },
+    #[snafu(display("{message}: {source}"))]
+    Keystore {
+        message: String,
+        source: WalletError,
+    },
+    #[snafu(display("Could not connect to the Rollup API at {url}. Ensure that the API is running and that the supplied URL is correct."))]
+    Unreachable { url: Url },
+    #[snafu(display("{source}"))]
+    Api { source: ApiError },
+    #[snafu(display("Transaction {nonce} rejected in block {height}: {error}"))]
+    RejectedInBlock {
+        nonce: Nonce,
+        height: u64,
+        error: RollupError,
+    },
+    #[snafu(display("Invalid signature: {source}"))]
+    InvalidSignature { source: RollupError },
+    #[snafu(display("Signed by {sender:?}, expected {expected:?}"))]
+    WrongSigner { sender: Address, expected: Address },
+    #[snafu(display("{message}"))]
+    Timeout { message: String },
+}
+
+impl From<ApiError> for CliError {
+    fn from(source: ApiError) -> Self {
+        Self::Api { source }
+    }
+}
+
+fn invalid_input(message: impl Into<String>) -> CliError {
+    CliError::InvalidInput {
+        message: message.into(),
+    }
+}
+
+impl CliError {
+    /// The class of the error, reported in JSON output.
+    fn class(&self) -> &'static str {
+        match self {
+            Self::InvalidInput { .. } => "invalid_input",
+            Self::Io { .. } | Self::Keystore { .. } => "local",
+            Self::Unreachable { .. }
+            | Self::Api {
+                source: ApiError::Server { .. },
+            } => "api",
+            Self::Api {
+                source: ApiError::Rejected { .. },
+            }
+            | Self::RejectedInBlock { .. } => "rejected",
+            Self::InvalidSignature { .. } | Self::WrongSigner { .. } => "verification",
+            Self::Timeout { .. } => "timeout",
+        }
+    }
+
+    /// The process exit code for the class of the error.
+    fn exit_code(&self) -> i32 {
+        match self {
+            Self::InvalidInput { .. } => 2,
+            Self::Io { .. } | Self::Keystore { .. } => 3,
+            Self::Unreachable { .. }
+            | Self::Api {
+                source: ApiError::Server { .. },
+            } => 4,
+            Self::Api {
+                source: ApiError::Rejected { .. },
+            }
+            | Self::RejectedInBlock { .. } => 5,
+            Self::InvalidSignature { .. } | Self::WrongSigner { .. } => 6,
+            Self::Timeout { .. } => 7,
+        }
+    }
+
+    fn rollup_error(&self) -> Option<&RollupError> {
+        match self {
+            Self::Api { source } => source.rollup_error(),
+            Self::RejectedInBlock { error, .. } => Some(error),
+            Self::InvalidSignature { source } => Some(source),
+            _ => None,
+        }
+    }
+
+    /// The error as reported with `--output json`.
+    fn report(&self) -> ErrorReport<'_> {
+        ErrorReport {
+            class: self.class(),
+            exit_code: self.exit_code(),
+            message: self.to_string(),
+            rollup_error: self.rollup_error(),
+            rollup_error_code: self.rollup_error().map(RollupError::code),
+        }
+    }
+}
+
+/// An error as reported with `--output json`.
+#[derive(Serialize)]
+struct ErrorReport<'a> {
+    class: &'static str,
+    exit_code: i32,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rollup_error: Option<&'a RollupError>,
+    /// The stable numeric code of `rollup_error`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rollup_error_code: Option<u16>,
+}
+
+/// The result of a command, printed as text or, with `--output json`, as JSON.
+///
+/// In JSON, the kind of result is given by the `type` field, e.g. `"type": "transfer"`.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CommandOutput {
+    Balance {
+        address: Address,
+        balance: Amount,
+    },
+    Transfer {
+        sender: Address,
+        destination: Address,
+        amount: Amount,
+        nonce: Nonce,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        outcome: Option<TransferOutcome>,
+    },
+    BatchTransfer {
+        sender: Address,
+        recipients: Vec<Recipient>,
+        total: Amount,
+        nonce: Nonce,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        outcome: Option<TransferOutcome>,
+    },
+    Signed(SignedTransaction),
+    SignedToFile {
+        sender: Address,
+        nonce: Nonce,
+        file: PathBuf,
+    },
+    MultisigCreated {
+        creator: Address,
+        address: Address,
+        owners: Vec<Address>,
+        threshold: u32,
+        nonce: Nonce,
+    },
+    Decoded {
+        sender: Option<Address>,
+        /// The owners who signed a multisig transaction.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        signers: Vec<Address>,
+        transaction: Transaction,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        ethereum_chain_id: Option<u64>,
+    },
+    Verified {
+        signer: Address,
+        /// Further owners who signed a multisig transaction.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        cosigners: Vec<Address>,
+    },
+    Wallet {
+        action: &'static str,
+        alias: String,
+        address: Address,
+    },
+    Wallets {
+        wallets: BTreeMap<String, Address>,
+    },
+    Address {
+        address: Address,
+    },
+}
+
+/// What `transfer --wait` observed.
+#[derive(Serialize)]
+pub struct TransferOutcome {
+    /// The HotShot height of the block which applied the transfer, if known.
+    height: Option<u64>,
+    /// Whether the block has been verified on L1.
+    verified: bool,
+}
+
+/// Describes the owners of a multisig account.
+fn describe_owners(multisig: &Multisig) -> String {
+    let owners: Vec<String> = multisig
+        .owners
+        .iter()
+        .map(|owner| format!("{owner:?}"))
+        .collect();
+    format!("{} of {}", multisig.threshold, owners.join(", "))
+}
+
+/// Appends what `transfer --wait` observed for transaction `nonce`, if anything.
+fn write_outcome(
+    f: &mut Formatter<'_>,
+    nonce: Nonce,
+    outcome: Option<&TransferOutcome>,
+) -> fmt::Result {
+    match outcome {
+        Some(TransferOutcome {
+            height: Some(height),
+            verified,
+        }) => {
+            write!(f, "\nTransaction {nonce} applied in block {height}")?;
+            if *verified {
+                write!(f, "\nBlock {height} verified on L1")?;
+            }
+        }
+        Some(TransferOutcome { height: None, .. }) => write!(f, "\nTransaction {nonce} applied")?,
+        None => {}
+    }
+    Ok(())
+}
+
+impl Display for CommandOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Balance { address, balance } => {
+                write!(f, "Balance of {:?}: {}", address, balance)
+            }
+            Self::Transfer {
+                sender,
+                destination,
+                amount,
+                nonce,
+                outcome,
+            } => {
+                write!(
+                    f,
+                    "Submitted Transaction to Rollup API: Transferring {} tokens from {} to {}",
+                    amount, sender, destination,
+                )?;
+                write_outcome(f, *nonce, outcome.as_ref())
+            }
+            Self::BatchTransfer {
+                sender,
+                recipients,
+                total,
+                nonce,
+                outcome,
+            } => {
+                write!(
+                    f,
+                    "Submitted Transaction to Rollup API: Transferring {} tokens from {} to {} recipients",
+                    total,
+                    sender,
+                    recipients.len(),
+                )?;
+                for recipient in recipients {
+                    write!(f, "\n  {} to {}", recipient.amount, recipient.destination)?;
+                }
+                write_outcome(f, *nonce, outcome.as_ref())
+            }
+            Self::Signed(signed_transaction) => write!(
+                f,
+                "{}",
+                serde_json::to_string_pretty(signed_transaction)
+                    .expect("Serialization should not fail")
+            ),
+            Self::SignedToFile {
+                sender,
+                nonce,
+                file,
+            } => write!(
+                f,
+                "Signed transaction {nonce} from {sender:?} written to {}",
+                file.display()
+            ),
+            Self::MultisigCreated {
+                creator,
+                address,
+                owners,
+                threshold,
+                ..
+            } => {
+                write!(
+                    f,
+                    "Submitted Transaction to Rollup API: Creating multisig account {address:?} \
+                     from {creator:?}, requiring {threshold} of {} owners",
+                    owners.len(),
+                )?;
+                for owner in owners {
+                    write!(f, "\n  Owner {owner:?}")?;
+                }
+                Ok(())
+            }
+            Self::Decoded {
+                sender,
+                signers,
+                transaction,
+                ethereum_chain_id,
+            } => {
+                let height = |height: Option<u64>| {
+                    height.map_or("-".to_string(), |height| height.to_string())
+                };
+                match sender {
+                    Some(sender) => writeln!(f, "Sender:      {sender:?}")?,
+                    None => writeln!(f, "Sender:      unknown (invalid signature)")?,
+                }
+                for signer in signers {
+                    writeln!(f, "Signed by:   {signer:?}")?;
+                }
+                writeln!(f, "Destination: {:?}", transaction.destination)?;
+                writeln!(f, "Amount:      {}", transaction.amount)?;
+                for recipient in &transaction.recipients {
+                    writeln!(f, "Destination: {:?}", recipient.destination)?;
+                    writeln!(f, "Amount:      {}", recipient.amount)?;
+                }
+                writeln!(f, "Nonce:       {}", transaction.nonce)?;
+                writeln!(f, "Valid after: {}", height(transaction.valid_after))?;
+                write!(f, "Valid until: {}", height(transaction.valid_until))?;
+                match &transaction.multisig_op {
+                    Some(MultisigOp::Create(multisig)) => {
+                        write!(f, "\nCreates multisig account")?;
+                        if let Some(sender) = sender {
+                            write!(f, " {:?}", Multisig::address(*sender, transaction.nonce))?;
+                        }
+                        write!(f, ": {}", describe_owners(multisig))?
+                    }
+                    Some(MultisigOp::SetOwners(multisig)) => {
+                        write!(f, "\nSets owners: {}", describe_owners(multisig))?
+                    }
+                    None => {}
+                }
+                if let Some(chain_id) = ethereum_chain_id {
+                    write!(
+                        f,
+                        "\nSigned as an Ethereum transaction for chain {chain_id}"
+                    )?;
+                }
+                Ok(())
+            }
+            Self::Verified { signer, cosigners } => {
+                write!(f, "Valid signature by {signer:?}")?;
+                for cosigner in cosigners {
+                    write!(f, "\nValid signature by {cosigner:?}")?;
+                }
+                Ok(())
+            }
+            Self::Wallet {
+                action,
+                alias,
+                address,
+            } => write!(f, "{action} wallet {alias}: {address:?}"),
+            Self::Wallets { wallets } => {
+                let lines: Vec<String> = wallets
+                    .iter()
+                    .map(|(alias, address)| format!("{alias}: {address:?}"))
+                    .collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            Self::Address { address } => write!(f, "{address:?}"),
+        }
+    }
+}
+
+/// The keystores managed by `wallet`, stored as `<alias>.json` in a directory.
+pub struct WalletDir {
+    dir: PathBuf,
+}
+
+impl WalletDir {
+    fn new(dir: Option<PathBuf>) -> Result<Self, CliError> {
+        let dir = match dir {
+            Some(dir) => dir,
+            None => std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| {
+                    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+                })
+                .ok_or_else(|| {
+                    invalid_input("Set HOME or --wallet-dir to locate the wallet directory")
+                })?
+                .join("example-rollup")
+                .join("wallets"),
+        };
+        Ok(Self { dir })
+    }
+
+    fn keystore_path(&self, alias: &str) -> PathBuf {
+        self.dir.join(format!("{alias}.json"))
+    }
+
+    fn index(&self) -> Result<BTreeMap<String, Address>, CliError> {
+        match fs::read(self.dir.join(WALLET_INDEX_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|err| CliError::Io {
+                message: "Error reading the wallet index".into(),
+                source: err.into(),
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Default::default()),
+            Err(source) => Err(CliError::Io {
+                message: "Error reading the wallet index".into(),
+                source,
+            }),
+        }
+    }
+
+    fn write_index(&self, index: &BTreeMap<String, Address>) -> Result<(), CliError> {
+        fs::write(
+            self.dir.join(WALLET_INDEX_FILE),
+            serde_json::to_vec_pretty(index).expect("Serialization should not fail"),
+        )
+        .context(IoSnafu {
+            message: "Error writing the wallet index",
+        })
+    }
+
+    fn address(&self, alias: &str) -> Result<Address, CliError> {
+        self.index()?.get(alias).copied().ok_or_else(|| {
+            invalid_input(format!(
+                "{alias} is not a preseeded identity, a hex encoded Ethereum address or a wallet alias"
+            ))
+        })
+    }
+
+    /// Encrypt a key and store it under a new alias.
+    fn store(&self, alias: &str, key: Option<&LocalWallet>) -> Result<Address, CliError> {
+        // Aliases must not be confused with identities or addresses when given as an `Account`
+        let valid = !alias.is_empty()
+            && alias
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            && matches!(Account::from_str(alias), Ok(Account::Alias(_)));
+        if !valid {
+            return Err(invalid_input(
+                "Aliases must consist of letters, digits, '-' and '_', and must not be a preseeded identity or an address",
+            ));
+        }
+        let mut index = self.index()?;
+        if index.contains_key(alias) {
+            return Err(invalid_input(format!("Wallet {alias} already exists")));
+        }
+
+        fs::create_dir_all(&self.dir).context(IoSnafu {
+            message: "Error creating the wallet directory",
+        })?;
+        let password = read_password(&format!("New password for {alias}: "), true)?;
+        let name = format!("{alias}.json");
+        let mut rng = rand::thread_rng();
+        let (wallet, _) = match key {
+            Some(key) => LocalWallet::encrypt_keystore(
+                &self.dir,
+                &mut rng,
+                key.signer().to_bytes(),
+                password,
+                Some(name.as_str()),
+            ),
+            None => LocalWallet::new_keystore(&self.dir, &mut rng, password, Some(name.as_str())),
+        }
+        .context(KeystoreSnafu {
+            message: "Error writing the keystore",
+        })?;
+        index.insert(alias.to_string(), wallet.address());
+        self.write_index(&index)?;
+        Ok(wallet.address())
+    }
+
+    fn unlock(&self, alias: &str) -> Result<LocalWallet, CliError> {
+        // Fail with a helpful message before prompting if the alias is unknown
+        self.address(alias)?;
+        let password = read_password(&format!("Password for {alias}: "), false)?;
+        LocalWallet::decrypt_keystore(self.keystore_path(alias), password).context(KeystoreSnafu {
+            message: format!("Error unlocking wallet {alias}"),
+        })
+    }
+
+    fn remove(&self, alias: &str) -> Result<Address, CliError> {
+        let mut index = self.index()?;
+        let address = index
+            .remove(alias)
+            .ok_or_else(|| invalid_input(format!("Wallet {alias} does not exist")))?;
+        fs::remove_file(self.keystore_path(alias)).context(IoSnafu {
+            message: "Error deleting the keystore",
+        })?;
+        self.write_index(&index)?;
+        Ok(address)
+    }
+}
+
+/// Read a keystore password from ESPRESSO_DEMO_KEYSTORE_PASSWORD, or prompt for it.
+fn read_password(prompt: &str, confirm: bool) -> Result<String, CliError> {
+    if let Ok(password) = std::env::var(KEYSTORE_PASSWORD_ENV) {
+        return Ok(password);
+    }
+    let message = "Error reading the password";
+    let password = rpassword::prompt_password(prompt).context(IoSnafu { message })?;
+    if confirm {
+        let confirmation =
+            rpassword::prompt_password("Repeat password: ").context(IoSnafu { message })?;
+        if password != confirmation {
+            return Err(invalid_input("Passwords do not match"));
+        }
+    }
+    Ok(password)
+}
+
+fn get_wallet_from_key(
+    key: &SenderKey,
+    index: u32,
+    wallets: &WalletDir,
+) -> Result<Wallet<SigningKey>, CliError> {
+    if let Some(account) = &key.from {
+        match account {
+            Account::Identity(identity) => Ok(get_wallet_from_identity(identity)),
+            Account::Alias(alias) => wallets.unlock(alias),
+            Account::Address(address) => Err(invalid_input(format!(
+                "Cannot sign as {address:?}, use a wallet alias or a key instead"
+            ))),
+        }
+    } else if let Some(private_key) = &key.private_key {
+        private_key
+            .parse()
+            .map_err(|_| invalid_input("Private key should be a hex encoded secp256k1 key"))
+    } else if let Some(mnemonic) = &key.mnemonic {
+        MnemonicBuilder::<English>::default()
+            .phrase(mnemonic.as_str())
+            .index(index)
+            .map_err(|err| invalid_input(format!("Invalid account index: {err}")))?
+            .build()
+            .map_err(|err| invalid_input(format!("Invalid mnemonic: {err}")))
+    } else if let Some(keystore) = &key.keystore {
+        let password = read_password(&format!("Password for {}: ", keystore.display()), false)?;
+        LocalWallet::decrypt_keystore(keystore, password).context(KeystoreSnafu {
+            message: format!("Error unlocking {}", keystore.display()),
+        })
+    } else {
+        unreachable!("clap requires exactly one sender key")
+    }
+}
+
+fn wallet(command: &WalletCommand, wallets: &WalletDir) -> Result<CommandOutput, CliError> {
+    let (action, alias, address) = match command {
+        WalletCommand::New { alias } => ("Created", alias, wallets.store(alias, None)?),
+        WalletCommand::Import { alias, key, index } => {
+            let key = get_wallet_from_key(key, *index, wallets)?;
+            ("Imported", alias, wallets.store(alias, Some(&key))?)
+        }
+        WalletCommand::List => {
+            return Ok(CommandOutput::Wallets {
+                wallets: wallets.index()?,
+            })
+        }
+        WalletCommand::ExportAddress { alias } => {
+            return Ok(CommandOutput::Address {
+                address: wallets.address(alias)?,
+            })
+        }
+        WalletCommand::Remove { alias } => ("Removed", alias, wallets.remove(alias)?),
+    };
+    Ok(CommandOutput::Wallet {
+        action,
+        alias: alias.clone(),
+        address,
+    })
+}
+
+async fn transfer(
+    transfer: &Transfer,
+    client: &RollupApiClient,
+    wallets: &WalletDir,
+) -> Result<CommandOutput, CliError> {
+    let sender = get_wallet_from_key(&transfer.sender, transfer.index, wallets)?;
+    let receiver = transfer.receiver.address(wallets)?;
     let amount = transfer.amount;
-    let nonce = get_nonce(&sender.address(), client).await + 1;
+    let nonce = client.nonce(sender.address()).await? + 1;
     let transaction = Transaction {
         amount,
-        destination: receiver.address(),
+        destination: receiver,
+        nonce,
+        valid_after: None,
+        valid_until: transfer.valid_until,
+        recipients: vec![],
+        multisig: None,
+        multisig_op: None,
+    };
+    let signed_transaction = SignedTransaction::new(transaction, &sender).await;
+    client.submit(&signed_transaction).await?;
+
+    let outcome = match transfer.wait {
+        Some(wait) => {
+            let deadline = Instant::now() + Duration::from_secs(transfer.timeout);
+            Some(
+                wait_for_transfer(
+                    client,
+                    sender.address(),
+                    &signed_transaction.transaction,
+                    wait,
+                    deadline,
+                )
+                .await?,
+            )
+        }
+        None => None,
+    };
+    Ok(CommandOutput::Transfer {
+        sender: sender.address(),
+        destination: receiver,
+        amount,
+        nonce,
+        outcome,
+    })
+}
+
+/// Reads the recipients of a batch transfer from a CSV file of `receiver,amount` rows.
+fn read_recipients(path: &Path, wallets: &WalletDir) -> Result<Vec<Recipient>, CliError> {
+    let csv = fs::read_to_string(path).context(IoSnafu {
+        message: "Error reading the recipients",
+    })?;
+    let mut recipients = vec![];
+    for (i, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid_row =
+            |reason: &str| invalid_input(format!("{}, line {}: {reason}", path.display(), i + 1));
+        let Some((receiver, amount)) = line.split_once(',') else {
+            return Err(invalid_row("expected `receiver,amount`"));
+        };
+        let (receiver, amount) = (receiver.trim(), amount.trim());
+        if recipients.is_empty() && amount.eq_ignore_ascii_case("amount") {
+            // Header row
+            continue;
+        }
+        let amount = amount
+            .parse()
+            .map_err(|_| invalid_row(&format!("invalid amount `{amount}`")))?;
+        let destination = Account::from_str(receiver)
+            .map_err(|err| invalid_row(&err))?
+            .address(wallets)?;
+        recipients.push(Recipient {
+            destination,
+            amount,
+        });
+    }
+    if recipients.is_empty() {
+        return Err(invalid_input(format!(
+            "{} does not list any recipients",
+            path.display()
+        )));
+    }
+    if recipients.len() > MAX_RECIPIENTS {
+        return Err(invalid_input(format!(
+            "{} lists {} recipients, at most {MAX_RECIPIENTS} are allowed",
+            path.display(),
+            recipients.len()
+        )));
+    }
+    Ok(recipients)
+}
+
+async fn transfer_batch(
+    transfer: &TransferBatch,
+    client: &RollupApiClient,
+    wallets: &WalletDir,
+) -> Result<CommandOutput, CliError> {
+    let sender = get_wallet_from_key(&transfer.sender, transfer.index, wallets)?;
+    let recipients = read_recipients(&transfer.recipients, wallets)?;
+    let nonce = client.nonce(sender.address()).await? + 1;
+    let mut transaction = Transaction::batch(recipients.clone(), nonce)
+        .expect("read_recipients returns at least one recipient");
+    transaction.valid_until = transfer.valid_until;
+    let total = transaction
+        .total_amount()
+        .ok_or_else(|| invalid_input("The total amount overflows"))?;
+    let signed_transaction = SignedTransaction::new(transaction, &sender).await;
+    client.submit(&signed_transaction).await?;
+
+    let outcome = match transfer.wait {
+        Some(wait) => {
+            let deadline = Instant::now() + Duration::from_secs(transfer.timeout);
+            Some(
+                wait_for_transfer(
+                    client,
+                    sender.address(),
+                    &signed_transaction.transaction,
+                    wait,
+                    deadline,
+                )
+                .await?,
+            )
+        }
+        None => None,
+    };
+    Ok(CommandOutput::BatchTransfer {
+        sender: sender.address(),
+        recipients,
+        total,
         nonce,
+        outcome,
+    })
+}
+
+/// Polls the rollup API until `transaction` from `sender` has been executed and, if requested,
+/// verified on L1. Fails if the transaction is rejected, or if `deadline` passes first.
+async fn wait_for_transfer(
+    client: &RollupApiClient,
+    sender: Address,
+    transaction: &Transaction,
+    wait: WaitFor,
+    deadline: Instant,
+) -> Result<TransferOutcome, CliError> {
+    let nonce = transaction.nonce;
+    let poll = |milestone: &'static str| async move {
+        if Instant::now() >= deadline {
+            return Err(CliError::Timeout {
+                message: format!(
+                    "Timed out waiting for transaction {nonce} from {sender:?} to be {milestone}"
+                ),
+            });
+        }
+        sleep(WAIT_POLL_INTERVAL).await;
+        Ok(())
+    };
+
+    let pending = client.pending(sender).await?;
+    if pending.iter().any(|txn| txn.transaction.nonce == nonce) {
+        eprintln!(
+            "Transaction {nonce} is held until transaction {} is applied",
+            nonce - 1
+        );
+    }
+
+    // Wait for the transaction to be executed
+    let height = loop {
+        let receipt = client.receipt(sender, nonce).await?.filter(|entry| {
+            // A rejection of an earlier transaction with the same nonce is not the outcome of this
+            // one, which may still be applied
+            entry.transaction.error.is_none()
+                || entry
+                    .transaction
+                    .transaction
+                    .as_ref()
+                    .is_some_and(|txn| &txn.transaction == transaction)
+        });
+        if let Some(entry) = receipt {
+            if let Some(error) = entry.transaction.error {
+                return Err(CliError::RejectedInBlock {
+                    nonce,
+                    height: entry.height,
+                    error,
+                });
+            }
+            break Some(entry.height);
+        }
+        // The block may not have been recorded by this node, e.g. if it was restarted
+        if client.nonce(sender).await? >= nonce {
+            break None;
+        }
+        poll("executed").await?;
+    };
+    if wait != WaitFor::Verified {
+        return Ok(TransferOutcome {
+            height,
+            verified: false,
+        });
+    }
+    let Some(height) = height else {
+        return Err(invalid_input(format!(
+            "Cannot wait for verification, the block of transaction {nonce} is unknown"
+        )));
+    };
+
+    // Wait for the block to be verified on L1
+    eprintln!("Transaction {nonce} applied in block {height}, waiting for verification");
+    loop {
+        let block = client.block(height).await?;
+        if block.is_some_and(|block| block.verified) {
+            return Ok(TransferOutcome {
+                height: Some(height),
+                verified: true,
+            });
+        }
+        poll("verified").await?;
+    }
+}
+
+/// Resolves the owners of a multisig account, checking them like the rollup would.
+fn multisig_owners(
+    owners: &[Account],
+    threshold: u32,
+    wallets: &WalletDir,
+) -> Result<Multisig, CliError> {
+    let multisig = Multisig {
+        owners: owners
+            .iter()
+            .map(|owner| owner.address(wallets))
+            .collect::<Result<_, _>>()?,
+        threshold,
+    };
+    multisig
+        .validate()
+        .map_err(|err| invalid_input(err.to_string()))?;
+    Ok(multisig)
+}
+
+/// Prints a signed transaction, or writes it to `out`.
+fn write_signed_transaction(
+    signed_transaction: SignedTransaction,
+    signer: Address,
+    out: Option<&Path>,
+) -> Result<CommandOutput, CliError> {
+    let Some(path) = out else {
+        return Ok(CommandOutput::Signed(signed_transaction));
+    };
+    let json =
+        serde_json::to_string_pretty(&signed_transaction).expect("Serialization should not fail");
+    fs::write(path, json).context(IoSnafu {
+        message: "Error writing the signed transaction",
+    })?;
+    Ok(CommandOutput::SignedToFile {
+        sender: signer,
+        nonce: signed_transaction.transaction.nonce,
+        file: path.to_path_buf(),
+    })
+}
+
+async fn sign(sign: &Sign, wallets: &WalletDir) -> Result<CommandOutput, CliError> {
+    let sender = get_wallet_from_key(&sign.sender, sign.index, wallets)?;
+    let multisig_op = match &sign.set_owners {
+        Some(owners) => {
+            let threshold = sign
+                .threshold
+                .expect("clap requires --threshold with --set-owners");
+            Some(MultisigOp::SetOwners(multisig_owners(
+                owners, threshold, wallets,
+            )?))
+        }
+        None => None,
+    };
+    let transaction = Transaction {
+        amount: sign.amount,
+        destination: sign.receiver.address(wallets)?,
+        nonce: sign.nonce,
+        valid_after: sign.valid_after,
+        valid_until: sign.valid_until,
+        recipients: vec![],
+        multisig: sign
+            .multisig
+            .as_ref()
+            .map(|account| account.address(wallets))
+            .transpose()?,
+        multisig_op,
     };
     let signed_transaction = SignedTransaction::new(transaction, &sender).await;
+    write_signed_transaction(signed_transaction, sender.address(), sign.out.as_deref())
+}
 
-    println!(
-        "Submitting Transaction to Rollup API: Transferring {} tokens from {} to {}",
+async fn cosign(cosign: &Cosign, wallets: &WalletDir) -> Result<CommandOutput, CliError> {
+    let owner = get_wallet_from_key(&cosign.sender, cosign.index, wallets)?;
+    let mut signed_transaction = read_signed_transaction(&cosign.signed)?;
+    if signed_transaction.transaction.multisig.is_none() {
+        return Err(invalid_input(
+            "Only transactions from multisig accounts can be cosigned",
+        ));
+    }
+    signed_transaction.cosign(&owner).await;
+    write_signed_transaction(signed_transaction, owner.address(), cosign.out.as_deref())
+}
+
+async fn create_multisig(
+    create: &CreateMultisig,
+    client: &RollupApiClient,
+    wallets: &WalletDir,
+) -> Result<CommandOutput, CliError> {
+    let creator = get_wallet_from_key(&create.sender, create.index, wallets)?;
+    let multisig = multisig_owners(&create.owners, create.threshold, wallets)?;
+    let nonce = client.nonce(creator.address()).await? + 1;
+    let address = Multisig::address(creator.address(), nonce);
+    let transaction = Transaction {
+        amount: create.amount,
+        destination: address,
+        nonce,
+        valid_after: None,
+        valid_until: None,
+        recipients: vec![],
+        multisig: None,
+        multisig_op: Some(MultisigOp::Create(multisig.clone())),
+    };
+    let signed_transaction = SignedTransaction::new(transaction, &creator).await;
+    client.submit(&signed_transaction).await?;
+    Ok(CommandOutput::MultisigCreated {
+        creator: creator.address(),
+        address,
+        owners: multisig.owners,
+        threshold: multisig.threshold,
+        nonce,
+    })
+}
+
+fn read_signed_transaction(signed: &SignedFile) -> Result<SignedTransaction, CliError> {
+    let json = if signed.file.as_os_str() == "-" {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(&signed.file)
+    }
+    .context(IoSnafu {
+        message: "Error reading the signed transaction",
+    })?;
+    serde_json::from_str(&json).map_err(|err| {
+        invalid_input(format!(
+            "File should contain a JSON serialized SignedTransaction: {err}"
+        ))
+    })
+}
+
+async fn broadcast(
+    signed: &SignedFile,
+    client: &RollupApiClient,
+) -> Result<CommandOutput, CliError> {
+    let signed_transaction = read_signed_transaction(signed)?;
+    let sender = signed_transaction
+        .recover()
+        .context(InvalidSignatureSnafu)?;
+    let total = signed_transaction
+        .transaction
+        .total_amount()
+        .ok_or_else(|| invalid_input("The total amount overflows"))?;
+    client.submit(&signed_transaction).await?;
+    let transaction = signed_transaction.transaction;
+    if !transaction.recipients.is_empty() {
+        return Ok(CommandOutput::BatchTransfer {
+            sender,
+            total,
+            recipients: transaction.transfers().collect(),
+            nonce: transaction.nonce,
+            outcome: None,
+        });
+    }
+    let Transaction {
         amount,
-        sender.address(),
-        receiver.address(),
-    );
+        destination,
+        nonce,
+        ..
+    } = transaction;
+    Ok(CommandOutput::Transfer {
+        sender,
+        destination,
+        amount,
+        nonce,
+        outcome: None,
+    })
+}
 
-    client
-        .post::<()>("rollup/submit")
-        .body_json(&signed_transaction)
-        .expect("Error setting the response body")
-        .send()
-        .await
-        .expect("Error sending the transfer transaction")
+fn decode(signed: &SignedFile) -> Result<CommandOutput, CliError> {
+    let signed_transaction = read_signed_transaction(signed)?;
+    let signers = match signed_transaction.transaction.multisig {
+        Some(_) => signed_transaction.signers().unwrap_or_default(),
+        None => vec![],
+    };
+    Ok(CommandOutput::Decoded {
+        sender: signed_transaction.recover().ok(),
+        signers,
+        ethereum_chain_id: signed_transaction.ethereum_chain_id(),
+        transaction: signed_transaction.transaction,
+    })
+}
+
+fn verify(verify: &Verify, wallets: &WalletDir) -> Result<CommandOutput, CliError> {
+    let signed_transaction = read_signed_transaction(&verify.signed)?;
+    let mut signers = signed_transaction
+        .signers()
+        .context(InvalidSignatureSnafu)?;
+    let sender = signers.remove(0);
+    if let Some(signer) = &verify.signer {
+        let expected = signer.address(wallets)?;
+        if sender != expected && !signers.contains(&expected) {
+            return Err(CliError::WrongSigner { sender, expected });
+        }
+    }
+    Ok(CommandOutput::Verified {
+        signer: sender,
+        cosigners: signers,
+    })
 }
 
-async fn get_nonce(address: &Address, client: &RollupClient) -> Nonce {
-    client
-        .get::<u64>(&format!("rollup/nonce/{:?}", address))
-        .send()
-        .await
-        .expect("Error sending the get nonce request")
+async fn check_balance(
+    check_balance: &CheckBalance,
+    client: &RollupApiClient,
+    wallets: &WalletDir,
+) -> Result<CommandOutput, CliError> {
+    let address = check_balance.account.address(wallets)?;
+    let balance = client.balance(address).await?;
+    Ok(CommandOutput::Balance { address, balance })
 }
 
-async fn check_balance(check_balance: &CheckBalance, client: &RollupClient) {
-    let address = get_wallet_from_identity(&check_balance.identity).address();
-    let balance = client
-        .get::<u64>(&format!("rollup/balance/{:?}", address))
-        .send()
-        .await
-        .expect("Error sending the check balance request");
+async fn run(
+    rollup_url: Url,
+    wallet_dir: Option<PathBuf>,
+    command: ExampleRollupCommand,
+) -> Result<CommandOutput, CliError> {
+    let wallets = WalletDir::new(wallet_dir)?;
+    // Offline commands, which don't need the rollup
+    match &command {
+        ExampleRollupCommand::Wallet(wallet_cmd) => return wallet(wallet_cmd, &wallets),
+        ExampleRollupCommand::Sign(sign_cmd) => return sign(sign_cmd, &wallets).await,
+        ExampleRollupCommand::Cosign(cosign_cmd) => return cosign(cosign_cmd, &wallets).await,
+        ExampleRollupCommand::Decode(decode_cmd) => return decode(decode_cmd),
+        ExampleRollupCommand::Verify(verify_cmd) => return verify(verify_cmd, &wallets),
+        _ => {}
+    }
+
+    let client = RollupApiClient::new(rollup_url.clone());
+    let connected = client.connect(Some(Duration::from_secs(2))).await;
+    if !connected {
+        return Err(CliError::Unreachable { url: rollup_url });
+    }
 
-    println!("Balance of {:?}: {}", address, balance)
+    match command {
+        ExampleRollupCommand::Transfer(transfer_cmd) => {
+            transfer(&transfer_cmd, &client, &wallets).await
+        }
+        ExampleRollupCommand::TransferBatch(transfer_cmd) => {
+            transfer_batch(&transfer_cmd, &client, &wallets).await
+        }
+        ExampleRollupCommand::CheckBalance(check_balance_cmd) => {
+            check_balance(&check_balance_cmd, &client, &wallets).await
+        }
+        ExampleRollupCommand::CreateMultisig(create_cmd) => {
+            create_multisig(&create_cmd, &client, &wallets).await
+        }
+        ExampleRollupCommand::Broadcast(broadcast_cmd) => broadcast(&broadcast_cmd, &client).await,
+        ExampleRollupCommand::Sign(_)
+        | ExampleRollupCommand::Cosign(_)
+        | ExampleRollupCommand::Decode(_)
+        | ExampleRollupCommand::Verify(_)
+        | ExampleRollupCommand::Wallet(_) => unreachable!("offline commands are handled above"),
+    }
 }
 
 #[async_std::main]
 async fn main() {
     let Options {
         rollup_url,
+        wallet_dir,
+        output,
         command,
     } = Options::parse();
-    let client: RollupClient = Client::new(rollup_url);
-    let connected = client.connect(Some(Duration::from_secs(2))).await;
-    if !connected {
-        println!("Could not connect to the Rollup Client. Ensure that the client is running and that the supplied port is correct.");
-        return;
+
+    match run(rollup_url, wallet_dir, command).await {
+        Ok(result) => match output {
+            OutputFormat::Text => println!("{result}"),
+            OutputFormat::Json => println!(
+                "{}",
+                serde_json::to_string(&result).expect("Serialization should not fail")
+            ),
+        },
+        Err(err) => {
+            match output {
+                OutputFormat::Text => eprintln!("Error: {err}"),
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string(&err.report()).expect("Serialization should not fail")
+                ),
+            }
+            std::process::exit(err.exit_code());
+        }
     }
+}
 
-    match command {
-        ExampleRollupCommand::Transfer(transfer_cmd) => transfer(&transfer_cmd, &client).await,
-        ExampleRollupCommand::CheckBalance(check_balance_cmd) => {
-            check_balance(&check_balance_cmd, &client).await;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use tide_disco::StatusCode;
+
+    fn wallet_dir() -> (TempDir, WalletDir) {
+        std::env::set_var(KEYSTORE_PASSWORD_ENV, "password");
+        let tmp = TempDir::new().unwrap();
+        let wallets = WalletDir::new(Some(tmp.path().join("wallets"))).unwrap();
+        (tmp, wallets)
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let address = get_wallet_from_identity(&SeedIdentity::Bob).address();
+        let rollup_error = RollupError::InsufficientBalance { address };
+        let cases = [
+            (invalid_input("bad input"), "invalid_input", 2),
+            (
+                CliError::Io {
+                    message: "io".into(),
+                    source: io::ErrorKind::NotFound.into(),
+                },
+                "local",
+                3,
+            ),
+            (
+                CliError::Keystore {
+                    message: "keystore".into(),
+                    source: WalletError::IoError(io::ErrorKind::NotFound.into()),
+                },
+                "local",
+                3,
+            ),
+            (
+                CliError::Unreachable {
+                    url: "http://localhost:8084".parse().unwrap(),
+                },
+                "api",
+                4,
+            ),
+            (
+                ApiError::Server {
+                    status: StatusCode::InternalServerError,
+                    message: "server".into(),
+                }
+                .into(),
+                "api",
+                4,
+            ),
+            (
+                ApiError::Rejected {
+                    error: rollup_error.clone(),
+                }
+                .into(),
+                "rejected",
+                5,
+            ),
+            (
+                CliError::RejectedInBlock {
+                    nonce: 1,
+                    height: 2,
+                    error: rollup_error.clone(),
+                },
+                "rejected",
+                5,
+            ),
+            (
+                CliError::InvalidSignature {
+                    source: RollupError::SignatureError,
+                },
+                "verification",
+                6,
+            ),
+            (
+                CliError::WrongSigner {
+                    sender: address,
+                    expected: Address::zero(),
+                },
+                "verification",
+                6,
+            ),
+            (
+                CliError::Timeout {
+                    message: "timeout".into(),
+                },
+                "timeout",
+                7,
+            ),
+        ];
+        for (err, class, exit_code) in cases {
+            assert_eq!(err.class(), class, "{err}");
+            assert_eq!(err.exit_code(), exit_code, "{err}");
         }
-    };
+    }
+
+    #[test]
+    fn test_error_report() {
+        let address = get_wallet_from_identity(&SeedIdentity::Bob).address();
+        let err = CliError::RejectedInBlock {
+            nonce: 1,
+            height: 2,
+            error: RollupError::InsufficientBalance { address },
+        };
+        let report = serde_json::to_value(err.report()).unwrap();
+        assert_eq!(report["class"], "rejected");
+        assert_eq!(report["exit_code"], 5);
+        assert_eq!(report["message"], err.to_string());
+        assert_eq!(report["rollup_error_code"], 2);
+        assert_eq!(
+            serde_json::from_value::<RollupError>(report["rollup_error"].clone()).unwrap(),
+            RollupError::InsufficientBalance { address }
+        );
+
+        // Errors not caused by the rollup omit the rollup error fields
+        let report = serde_json::to_value(invalid_input("bad input").report()).unwrap();
+        assert_eq!(
+            report,
+            serde_json::json!({
+                "class": "invalid_input",
+                "exit_code": 2,
+                "message": "bad input",
+            })
+        );
+    }
+
+    #[test]
+    fn test_command_output_json() {
+        let address = get_wallet_from_identity(&SeedIdentity::Bob).address();
+        let output = serde_json::to_value(CommandOutput::Balance {
+            address,
+            balance: 10,
+        })
+        .unwrap();
+        assert_eq!(
+            output,
+            serde_json::json!({
+                "type": "balance",
+                "address": address,
+                "balance": 10,
+            })
+        );
+
+        // Results are tagged in snake case, and the outcome is omitted unless waited for
+        let mut transfer = serde_json::to_value(CommandOutput::Transfer {
+            sender: address,
+            destination: Address::zero(),
+            amount: 5,
+            nonce: 1,
+            outcome: None,
+        })
+        .unwrap();
+        assert_eq!(
+            transfer,
+            serde_json::json!({
+                "type": "transfer",
+                "sender": address,
+                "destination": Address::zero(),
+                "amount": 5,
+                "nonce": 1,
+            })
+        );
+        transfer = serde_json::to_value(CommandOutput::BatchTransfer {
+            sender: address,
+            recipients: vec![],
+            total: 0,
+            nonce: 1,
+            outcome: Some(TransferOutcome {
+                height: Some(3),
+                verified: true,
+            }),
+        })
+        .unwrap();
+        assert_eq!(transfer["type"], "batch_transfer");
+        assert_eq!(
+            transfer["outcome"],
+            serde_json::json!({ "height": 3, "verified": true })
+        );
+
+        let wallets = serde_json::to_value(CommandOutput::Wallets {
+            wallets: BTreeMap::from([("alias".to_string(), address)]),
+        })
+        .unwrap();
+        assert_eq!(
+            wallets,
+            serde_json::json!({
+                "type": "wallets",
+                "wallets": { "alias": address },
+            })
+        );
+    }
+
+    fn recipients_file(tmp: &TempDir, csv: &str) -> PathBuf {
+        let path = tmp.path().join("recipients.csv");
+        fs::write(&path, csv).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_read_recipients() {
+        let (tmp, wallets) = wallet_dir();
+        let alias = get_wallet_from_identity(&SeedIdentity::Charlie);
+        wallets.store("carol", Some(&alias)).unwrap();
+        let bob = get_wallet_from_identity(&SeedIdentity::Bob).address();
+        let address = Address::repeat_byte(1);
+
+        // Header, comment and blank lines are skipped, and receivers may be given as identities,
+        // hex addresses or aliases
+        let csv = format!(
+            "receiver,amount\n\
+             # Comment\n\
+             \n\
+             Bob, 1\n  \n\
+             {address:?},2\n\
+             carol ,3\n"
+        );
+        let recipients = read_recipients(&recipients_file(&tmp, &csv), &wallets).unwrap();
+        assert_eq!(
+            recipients,
+            [
+                Recipient {
+                    destination: bob,
+                    amount: 1
+                },
+                Recipient {
+                    destination: address,
+                    amount: 2
+                },
+                Recipient {
+                    destination: alias.address(),
+                    amount: 3
+                },
+            ]
+        );
+
+        // The header is optional
+        let recipients = read_recipients(&recipients_file(&tmp, "Bob,1"), &wallets).unwrap();
+        assert_eq!(recipients.len(), 1);
+    }
+
+    #[test]
+    fn test_read_recipients_invalid() {
+        let (tmp, wallets) = wallet_dir();
+        let address = Address::repeat_byte(1);
+        for csv in [
+            // No recipients
+            "",
+            "receiver,amount\n\n# Comment\n",
+            // Bad amounts
+            "Bob,-1",
+            "Bob,1.5",
+            "Bob,",
+            "Bob,18446744073709551616",
+            // The header must come first
+            "Bob,1\nreceiver,amount",
+            // Bad rows and receivers
+            "Bob",
+            "Bob;1",
+            "unknown,1",
+        ] {
+            let err = read_recipients(&recipients_file(&tmp, csv), &wallets).unwrap_err();
+            assert_eq!(err.exit_code(), 2, "{csv}: {err}");
+        }
+
+        // Rows are reported by line number
+        let err = read_recipients(&recipients_file(&tmp, "Bob,1\n\nBob,x"), &wallets).unwrap_err();
+        assert!(
+            err.to_string().ends_with("line 3: invalid amount `x`"),
+            "{err}"
+        );
+
+        // At most `MAX_RECIPIENTS` recipients
+        let row = format!("{address:?},1\n");
+        let csv = row.repeat(MAX_RECIPIENTS);
+        let recipients = read_recipients(&recipients_file(&tmp, &csv), &wallets).unwrap();
+        assert_eq!(recipients.len(), MAX_RECIPIENTS);
+        let csv = row.repeat(MAX_RECIPIENTS + 1);
+        let err = read_recipients(&recipients_file(&tmp, &csv), &wallets).unwrap_err();
+        assert_eq!(err.exit_code(), 2, "{err}");
+
+        // A missing file is a local error
+        let err = read_recipients(&tmp.path().join("missing.csv"), &wallets).unwrap_err();
+        assert_eq!(err.exit_code(), 3, "{err}");
+    }
+
+    #[test]
+    fn test_wallet_round_trip() {
+        let (_tmp, wallets) = wallet_dir();
+        let key = get_wallet_from_identity(&SeedIdentity::Alice);
+
+        // Import a key and create a new one
+        assert_eq!(
+            wallets.store("imported", Some(&key)).unwrap(),
+            key.address()
+        );
+        let created = wallets.store("created", None).unwrap();
+        assert_ne!(created, key.address());
+        assert!(wallets.keystore_path("imported").exists());
+        assert!(wallets.keystore_path("created").exists());
+
+        // Both are listed in the index, which is persisted in `addresses.json`
+        let index = wallets.index().unwrap();
+        assert_eq!(
+            index,
+            BTreeMap::from([
+                ("created".to_string(), created),
+                ("imported".to_string(), key.address())
+            ])
+        );
+        let bytes = fs::read(wallets.dir.join(WALLET_INDEX_FILE)).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<BTreeMap<String, Address>>(&bytes).unwrap(),
+            index
+        );
+        assert_eq!(wallets.address("imported").unwrap(), key.address());
+
+        // The keystores unlock to the stored keys
+        assert_eq!(wallets.unlock("imported").unwrap().address(), key.address());
+        assert_eq!(wallets.unlock("created").unwrap().address(), created);
+
+        // An alias cannot be reused
+        let err = wallets.store("imported", None).unwrap_err();
+        assert_eq!(err.exit_code(), 2, "{err}");
+
+        // Removing a wallet deletes its keystore and its index entry
+        assert_eq!(wallets.remove("imported").unwrap(), key.address());
+        assert!(!wallets.keystore_path("imported").exists());
+        assert_eq!(
+            wallets.index().unwrap(),
+            BTreeMap::from([("created".to_string(), created)])
+        );
+        wallets.address("imported").unwrap_err();
+        wallets.unlock("imported").unwrap_err();
+        wallets.remove("imported").unwrap_err();
+
+        // The alias can be used again once removed
+        assert_eq!(
+            wallets.store("imported", Some(&key)).unwrap(),
+            key.address()
+        );
+    }
+
+    #[test]
+    fn test_wallet_empty_dir() {
+        let (_tmp, wallets) = wallet_dir();
+        assert!(wallets.index().unwrap().is_empty());
+        wallets.address("missing").unwrap_err();
+        wallets.remove("missing").unwrap_err();
+    }
+
+    #[test]
+    fn test_wallet_alias_validation() {
+        let (_tmp, wallets) = wallet_dir();
+        let address = format!(
+            "{:?}",
+            get_wallet_from_identity(&SeedIdentity::Bob).address()
+        );
+        for alias in [
+            "",
+            "with space",
+            "../escape",
+            "dot.json",
+            "Alice",
+            address.as_str(),
+        ] {
+            let err = wallets.store(alias, None).unwrap_err();
+            assert_eq!(err.exit_code(), 2, "{alias}: {err}");
+        }
+        // Nothing was written for the invalid aliases
+        assert!(!wallets.dir.exists());
+
+        for alias in ["alice", "my-wallet_2"] {
+            wallets.store(alias, None).unwrap();
+        }
+        assert_eq!(wallets.index().unwrap().len(), 2);
+    }
 }
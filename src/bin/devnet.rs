@@ -0,0 +1,239 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! A local development network.
+//!
+//! The devnet runs the whole stack in a single process, without Docker or an external L1: an Anvil
+//! node with the HotShot contract deployed, an in-memory HotShot network with its query service,
+//! the task committing HotShot blocks to L1, and the example rollup with its executor and API.
+
+use async_compatibility_layer::{
+    async_primitives::broadcast,
+    logging::{setup_backtrace, setup_logging},
+};
+use async_std::{sync::RwLock, task::sleep};
+use clap::Parser;
+use commit::Committable;
+use ethers::{
+    providers::Middleware,
+    signers::{coins_bip39::English, MnemonicBuilder, Signer},
+    types::BlockNumber,
+};
+use example_l2::{
+    api::{serve, APIOptions},
+    blocks::BlockStore,
+    executor::{run_executor, ExecutorOptions},
+    genesis::Genesis,
+    status::StatusOptions,
+    utils::{create_provider, deploy_example_contract},
+};
+use futures::{future::ready, join, FutureExt};
+use sequencer::{
+    api::options::{Http, Options as SequencerApiOptions},
+    context::SequencerContext,
+    hotshot_commitment::{run_hotshot_commitment_task, CommitmentTaskOptions},
+    persistence::fs,
+    testing::init_hotshot_handles,
+};
+use sequencer_utils::{test_utils::TestL1System, Anvil, AnvilOptions};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use surf_disco::Url;
+
+/// Mnemonic of the accounts funded by Anvil.
+const ANVIL_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+#[derive(Parser, Clone, Debug)]
+struct Options {
+    /// Port on which to serve the rollup API.
+    #[clap(long, env = "ESPRESSO_DEMO_ROLLUP_PORT", default_value = "8084")]
+    api_port: u16,
+
+    /// Port on which to serve the sequencer API.
+    #[clap(long, env = "ESPRESSO_DEMO_SEQUENCER_PORT", default_value = "50000")]
+    sequencer_port: u16,
+
+    /// JSON file with the VM ID and initial balances of the rollup.
+    /// If not provided, the demo genesis is used: VM ID 1, with each preseeded identity funded.
+    #[clap(long, env = "ESPRESSO_DEMO_GENESIS_FILE")]
+    genesis_file: Option<PathBuf>,
+
+    /// Directory in which the sequencer query service stores its data, kept across runs.
+    /// Defaults to a fresh temporary directory, removed on exit.
+    #[clap(long, env = "ESPRESSO_DEMO_DEVNET_STORAGE_PATH")]
+    storage_path: Option<PathBuf>,
+
+    /// Check the invariants of the state machine after executing each block.
+    #[clap(long, env = "ESPRESSO_DEMO_CHECK_INVARIANTS")]
+    check_invariants: bool,
+
+    /// Interval between L1 blocks, in seconds.
+    #[clap(long, default_value = "1")]
+    l1_block_time: u64,
+}
+
+/// Start an Anvil node, and wait until it is ready to serve requests.
+async fn spawn_anvil(block_time: Duration) -> Anvil {
+    let anvil = AnvilOptions::default().block_time(block_time).spawn().await;
+
+    // Some endpoints (e.g. eth_feeHistory) do not work until at least one block has been mined.
+    // Wait until the fee history endpoint works.
+    let provider = create_provider(&anvil.url());
+    while let Err(err) = provider.fee_history(1, BlockNumber::Latest, &[]).await {
+        tracing::warn!("L1 RPC is not ready: {err}");
+        sleep(Duration::from_secs(1)).await;
+    }
+
+    anvil
+}
+
+#[async_std::main]
+async fn main() {
+    setup_logging();
+    setup_backtrace();
+
+    let opt = Options::parse();
+    let genesis = match &opt.genesis_file {
+        Some(path) => Genesis::from_file(path).expect("Error reading the genesis file"),
+        None => Genesis::default(),
+    };
+    // Each run starts a new HotShot network, so unless a storage path is given, the query service
+    // starts from a fresh directory rather than on top of data from an earlier run.
+    let temp_storage;
+    let storage_path = match &opt.storage_path {
+        Some(path) => path.clone(),
+        None => {
+            temp_storage = tempfile::Builder::new()
+                .prefix("example-rollup-devnet")
+                .tempdir()
+                .expect("Error creating the query service storage directory");
+            temp_storage.path().to_path_buf()
+        }
+    };
+
+    // Start L1 and deploy the HotShot contract
+    tracing::info!("Starting L1");
+    let anvil = spawn_anvil(Duration::from_secs(opt.l1_block_time)).await;
+    let l1_http_provider = anvil.url();
+    let l1_ws_provider = anvil.ws_url();
+    let test_l1 = TestL1System::deploy(create_provider(&l1_http_provider))
+        .await
+        .unwrap();
+    let hotshot_address = test_l1.hotshot.address();
+
+    // Start HotShot and the sequencer query service. The handles must be kept alive for the
+    // network to keep running.
+    tracing::info!("Starting HotShot");
+    let nodes = init_hotshot_handles().await;
+    let api_node = nodes[0].clone();
+    let init_handle = Box::new(move |_| {
+        ready(SequencerContext::new(
+            api_node,
+            0,
+            Default::default(),
+            Default::default(),
+            None,
+        ))
+        .boxed()
+    });
+    SequencerApiOptions::from(Http {
+        port: opt.sequencer_port,
+    })
+    .submit(Default::default())
+    .status(Default::default())
+    .query_fs(
+        Default::default(),
+        fs::Options {
+            path: storage_path.clone(),
+        },
+    )
+    .serve(init_handle)
+    .await
+    .unwrap();
+    for node in &nodes {
+        node.hotshot.start_consensus().await;
+    }
+    let sequencer_url: Url = format!("http://localhost:{}", opt.sequencer_port)
+        .parse()
+        .unwrap();
+
+    // Deploy the rollup contract with the genesis state
+    tracing::info!("Deploying Rollup contracts");
+    let state = genesis.state();
+    let rollup_contract = deploy_example_contract(&test_l1, state.commit()).await;
+    let state = Arc::new(RwLock::new(state));
+
+    // Commit HotShot blocks to L1 from the first funded account, and send proofs to the rollup
+    // contract from the second
+    let hotshot_options = CommitmentTaskOptions {
+        l1_provider: l1_http_provider.clone(),
+        sequencer_mnemonic: ANVIL_MNEMONIC.to_string(),
+        sequencer_account_index: test_l1.clients.funded[0].index,
+        hotshot_address,
+        l1_chain_id: None,
+        query_service_url: Some(sequencer_url.clone()),
+        delay: None,
+    };
+    let rollup_account_index = test_l1.clients.funded[1].index;
+    let prover_address = MnemonicBuilder::<English>::default()
+        .phrase(ANVIL_MNEMONIC)
+        .index(rollup_account_index)
+        .unwrap()
+        .build()
+        .unwrap()
+        .address();
+
+    let (executor_send, _) = broadcast::channel();
+    let block_store = Arc::new(RwLock::new(BlockStore::default()));
+    let executor_options = ExecutorOptions {
+        hotshot_address,
+        l1_http_provider: l1_http_provider.clone(),
+        l1_ws_provider: l1_ws_provider.clone(),
+        rollup_address: rollup_contract.address(),
+        rollup_account_index,
+        rollup_mnemonic: ANVIL_MNEMONIC.to_string(),
+        sequencer_url: sequencer_url.clone(),
+        output_stream: Some(executor_send.clone()),
+        block_store: block_store.clone(),
+        check_invariants: opt.check_invariants,
+    };
+    let api_options = APIOptions {
+        api_port: opt.api_port,
+        sequencer_url: sequencer_url.clone(),
+        executor_stream: Some(executor_send.clone()),
+        block_store: block_store.clone(),
+        status_options: Some(StatusOptions {
+            l1_http_provider: l1_http_provider.clone(),
+            l1_ws_provider: l1_ws_provider.clone(),
+            hotshot_address,
+            rollup_address: rollup_contract.address(),
+            prover_address,
+        }),
+    };
+
+    let rollup_url = format!("http://localhost:{}", opt.api_port);
+    println!("Example Rollup devnet is running");
+    println!("  Rollup API:         {rollup_url}");
+    println!("  Ethereum JSON-RPC:  {rollup_url}/eth/rpc");
+    println!("  Metrics:            {rollup_url}/rollup/metrics");
+    println!("  Sequencer API:      {sequencer_url}");
+    println!("  L1 HTTP provider:   {l1_http_provider}");
+    println!("  L1 WS provider:     {l1_ws_provider}");
+    println!("  HotShot contract:   {hotshot_address:?}");
+    println!("  Rollup contract:    {:?}", rollup_contract.address());
+    println!("  Rollup VM ID:       {}", genesis.vm_id);
+    println!("  Rollup chain ID:    {}", genesis.chain_id);
+    println!("  Query storage:      {}", storage_path.display());
+    println!("  Genesis balances:");
+    for (address, balance) in &genesis.balances {
+        println!("    {address:?}: {balance}");
+    }
+
+    join!(
+        run_hotshot_commitment_task(&hotshot_options),
+        run_executor(&executor_options, state.clone()),
+        async { serve(&api_options, state.clone()).await.unwrap() },
+    );
+}
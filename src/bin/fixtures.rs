@@ -0,0 +1,135 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Record the HotShot blocks executed by a rollup to a file, and replay them offline.
+
+use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+use clap::{Parser, Subcommand};
+use contract_bindings::example_rollup::ExampleRollup;
+use ethers::types::Address;
+use example_l2::{
+    fixtures::Fixtures, genesis::Genesis, query::HotShotClient, utils::create_provider,
+};
+use std::{path::PathBuf, process::exit, sync::Arc};
+use surf_disco::Url;
+
+#[derive(Parser, Clone, Debug)]
+struct Options {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum Command {
+    /// Record the blocks executed by a rollup and the state commitments accepted by its contract.
+    Record {
+        /// URL of the HotShot sequencer node.
+        #[clap(
+            long,
+            env = "ESPRESSO_SEQUENCER_URL",
+            default_value = "http://localhost:50000"
+        )]
+        sequencer_url: Url,
+
+        /// URL of layer 1 Ethereum JSON-RPC provider.
+        #[clap(
+            long,
+            env = "ESPRESSO_DEMO_L1_HTTP_PROVIDER",
+            default_value = "http://localhost:8545"
+        )]
+        l1_http_provider: Url,
+
+        /// Address of the rollup contract.
+        #[clap(long, env = "ESPRESSO_DEMO_ROLLUP_ADDRESS")]
+        rollup_address: Address,
+
+        /// JSON file with the VM ID and initial balances of the rollup.
+        /// If not provided, the demo genesis is used.
+        #[clap(long, env = "ESPRESSO_DEMO_GENESIS_FILE")]
+        genesis_file: Option<PathBuf>,
+
+        /// Height of the first block to record. The blocks before it are executed to take a
+        /// snapshot of the state, from which replay starts.
+        #[clap(long, default_value = "0")]
+        from: u64,
+
+        /// Number of blocks to record. Defaults to every block verified by the rollup contract.
+        #[clap(long)]
+        blocks: Option<u64>,
+
+        /// File to write the fixtures to.
+        #[clap(long)]
+        out: PathBuf,
+    },
+    /// Execute recorded blocks and check the resulting state commitments.
+    Replay {
+        /// Fixture file written by `record`.
+        file: PathBuf,
+    },
+}
+
+#[async_std::main]
+async fn main() {
+    setup_logging();
+    setup_backtrace();
+
+    match Options::parse().command {
+        Command::Record {
+            sequencer_url,
+            l1_http_provider,
+            rollup_address,
+            genesis_file,
+            from,
+            blocks,
+            out,
+        } => {
+            let genesis = match genesis_file {
+                Some(path) => Genesis::from_file(path).expect("Error reading the genesis file"),
+                None => Genesis::default(),
+            };
+            let hotshot = HotShotClient::new(sequencer_url.join("availability").unwrap());
+            hotshot.connect(None).await;
+            let rollup_contract =
+                ExampleRollup::new(rollup_address, Arc::new(create_provider(&l1_http_provider)));
+
+            let fixtures =
+                match Fixtures::record(&hotshot, &rollup_contract, genesis, from, blocks).await {
+                    Ok(fixtures) => fixtures,
+                    Err(err) => {
+                        eprintln!("{err}");
+                        exit(1);
+                    }
+                };
+            if let Err(err) = fixtures.save(&out) {
+                eprintln!("{err}");
+                exit(1);
+            }
+            println!(
+                "Recorded {} blocks from block {} and {} state updates to {}",
+                fixtures.blocks.len(),
+                fixtures.start,
+                fixtures.state_updates.len(),
+                out.display()
+            );
+        }
+        Command::Replay { file } => {
+            let report = match Fixtures::load(&file) {
+                Ok(fixtures) => fixtures.replay().await,
+                Err(err) => Err(err),
+            };
+            match report {
+                Ok(report) => println!(
+                    "Replayed {} blocks, {} state updates match. Final state commitment: {}",
+                    report.blocks, report.state_updates, report.state_commitment
+                ),
+                Err(err) => {
+                    eprintln!("{err}");
+                    exit(1);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,419 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+use commit::Commitment;
+use ethers::{abi::Address, types::H256};
+use sequencer::NMTRoot;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ops::RangeInclusive;
+use std::time::SystemTime;
+
+use crate::error::RollupError;
+use crate::state::State;
+use crate::transaction::SignedTransaction;
+
+/// The outcome of a single transaction from the rollup's namespace of a block.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExecutedTransaction {
+    /// The decoded transaction, or `None` if the namespace leaf was malformed.
+    pub transaction: Option<SignedTransaction>,
+    /// The recovered sender, if the signature is valid.
+    pub sender: Option<Address>,
+    /// The reason the transaction was rejected, or `None` if it was applied.
+    pub error: Option<RollupError>,
+}
+
+/// A block executed by the rollup, as recorded by the executor.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExecutedBlock {
+    /// The HotShot height of the block.
+    pub height: u64,
+    /// Commitment to the block's transaction NMT.
+    pub block: Commitment<NMTRoot>,
+    /// The state commitment before the block was executed.
+    pub old_state: Commitment<State>,
+    /// The state commitment after the block was executed.
+    pub new_state: Commitment<State>,
+    /// The transactions in the rollup's namespace, in order, with their outcomes.
+    pub transactions: Vec<ExecutedTransaction>,
+    /// Whether a proof covering this block has been verified by the rollup contract on L1.
+    pub verified: bool,
+}
+
+/// A transaction sent or received by an address, as listed in its history.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// The HotShot height of the block containing the transaction.
+    pub height: u64,
+    /// The position of the transaction within the rollup's namespace of the block.
+    pub index: usize,
+    pub transaction: ExecutedTransaction,
+}
+
+/// A page of the transaction history of an address, newest first.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryPage {
+    pub entries: Vec<HistoryEntry>,
+    /// The cursor to pass to fetch the next (older) page, or `None` if this is the last page.
+    pub next_cursor: Option<usize>,
+}
+
+/// The number of most recent blocks retained by a `BlockStore` by default.
+pub const DEFAULT_RETAINED_BLOCKS: usize = 10_000;
+
+/// An in-memory index of the blocks executed by the rollup, keyed by HotShot height, of the
+/// transactions sent or received by each address, and of transactions translated from Ethereum by
+/// their Ethereum hash.
+///
+/// Only the most recent `retained_blocks` blocks are kept. Older blocks, and the index entries of
+/// their transactions, are pruned as new blocks are recorded.
+#[derive(Debug)]
+pub struct BlockStore {
+    blocks: BTreeMap<u64, ExecutedBlock>,
+    history: HashMap<Address, AddressHistory>,
+    ethereum_transactions: HashMap<H256, (u64, usize)>,
+    retained_blocks: usize,
+    last_verification: Option<SystemTime>,
+}
+
+/// Locations (height, index) of the transactions of an address, oldest first.
+///
+/// Positions in the full history of the address are stable, and are used as pagination cursors.
+/// Pruned entries are only counted, so that positions are not shifted.
+#[derive(Debug, Default)]
+struct AddressHistory {
+    pruned: usize,
+    locations: VecDeque<(u64, usize)>,
+}
+
+impl Default for BlockStore {
+    fn default() -> Self {
+        Self::with_retention(DEFAULT_RETAINED_BLOCKS)
+    }
+}
+
+impl BlockStore {
+    /// Create a store which keeps the `retained_blocks` most recent blocks, and at least one.
+    pub fn with_retention(retained_blocks: usize) -> Self {
+        Self {
+            blocks: Default::default(),
+            history: Default::default(),
+            ethereum_transactions: Default::default(),
+            retained_blocks: retained_blocks.max(1),
+            last_verification: None,
+        }
+    }
+
+    /// Record an executed block, indexing its transactions by sender and destinations.
+    ///
+    /// Blocks are recorded in height order. A block at or below the last recorded height is
+    /// ignored, so that its transactions are not indexed twice. If more than `retained_blocks`
+    /// blocks are recorded, the oldest is pruned.
+    pub fn insert(&mut self, block: ExecutedBlock) {
+        if self
+            .last_executed_block()
+            .is_some_and(|last| block.height <= last)
+        {
+            return;
+        }
+        for (index, executed) in block.transactions.iter().enumerate() {
+            for address in addresses(executed) {
+                self.history
+                    .entry(address)
+                    .or_default()
+                    .locations
+                    .push_back((block.height, index));
+            }
+        }
+        let height = block.height;
+        self.blocks.insert(height, block);
+
+        // The same Ethereum transaction may be sequenced more than once. The first time it was
+        // applied is the one that counts, so only a rejection is replaced.
+        for (index, executed) in self.blocks[&height].transactions.iter().enumerate() {
+            let Some(hash) = ethereum_hash(executed) else {
+                continue;
+            };
+            let applied = self
+                .ethereum_transactions
+                .get(&hash)
+                .is_some_and(|&(location, i)| {
+                    self.blocks[&location].transactions[i].error.is_none()
+                });
+            if !applied {
+                self.ethereum_transactions.insert(hash, (height, index));
+            }
+        }
+
+        while self.blocks.len() > self.retained_blocks {
+            let (height, pruned) = self.blocks.pop_first().expect("Store is not empty");
+            for address in pruned.transactions.iter().flat_map(addresses) {
+                let Some(history) = self.history.get_mut(&address) else {
+                    continue;
+                };
+                while history
+                    .locations
+                    .front()
+                    .is_some_and(|&(location, _)| location <= height)
+                {
+                    history.locations.pop_front();
+                    history.pruned += 1;
+                }
+                if history.locations.is_empty() {
+                    self.history.remove(&address);
+                }
+            }
+            for hash in pruned.transactions.iter().filter_map(ethereum_hash) {
+                if self
+                    .ethereum_transactions
+                    .get(&hash)
+                    .is_some_and(|&(location, _)| location == height)
+                {
+                    self.ethereum_transactions.remove(&hash);
+                }
+            }
+        }
+    }
+
+    /// Fetch a page of the transaction history of an address, newest first.
+    ///
+    /// Only transactions in retained blocks are listed.
+    ///
+    /// # Parameters
+    /// - `address`: The address whose transactions to list.
+    /// - `cursor`: The `next_cursor` of the previous page, or `None` to start from the newest transaction.
+    /// - `limit`: The maximum number of transactions to return, at least one.
+    pub fn history(&self, address: &Address, cursor: Option<usize>, limit: usize) -> HistoryPage {
+        let Some(history) = self.history.get(address) else {
+            return HistoryPage {
+                entries: vec![],
+                next_cursor: None,
+            };
+        };
+        let len = history.pruned + history.locations.len();
+        let end = cursor.unwrap_or(len).clamp(history.pruned, len);
+        let start = end.saturating_sub(limit.max(1)).max(history.pruned);
+        let entries = history
+            .locations
+            .range(start - history.pruned..end - history.pruned)
+            .rev()
+            .map(|&(height, index)| HistoryEntry {
+                height,
+                index,
+                transaction: self.blocks[&height].transactions[index].clone(),
+            })
+            .collect();
+        HistoryPage {
+            entries,
+            next_cursor: (start > history.pruned).then_some(start),
+        }
+    }
+
+    /// Find a retained transaction translated from Ethereum by its Ethereum hash.
+    ///
+    /// If the transaction was executed more than once, the entry in which it was applied is
+    /// returned, if any.
+    pub fn ethereum_transaction(&self, hash: &H256) -> Option<HistoryEntry> {
+        let &(height, index) = self.ethereum_transactions.get(hash)?;
+        Some(HistoryEntry {
+            height,
+            index,
+            transaction: self.blocks[&height].transactions[index].clone(),
+        })
+    }
+
+    /// Fetch the block at a HotShot height.
+    pub fn get(&self, height: u64) -> Option<&ExecutedBlock> {
+        self.blocks.get(&height)
+    }
+
+    /// Fetch the executed blocks with heights in `heights`, in order.
+    pub fn range(&self, heights: RangeInclusive<u64>) -> Vec<ExecutedBlock> {
+        self.blocks
+            .range(heights)
+            .map(|(_, block)| block.clone())
+            .collect()
+    }
+
+    /// Fetch the HotShot height of the last executed block.
+    pub fn last_executed_block(&self) -> Option<u64> {
+        self.blocks.keys().next_back().copied()
+    }
+
+    /// Fetch the time at which blocks were last verified on L1.
+    pub fn last_verification(&self) -> Option<SystemTime> {
+        self.last_verification
+    }
+
+    /// Mark the blocks with heights in `heights` as verified on L1.
+    pub fn mark_verified(&mut self, heights: RangeInclusive<u64>) {
+        for (_, block) in self.blocks.range_mut(heights) {
+            block.verified = true;
+        }
+        self.last_verification = Some(SystemTime::now());
+    }
+}
+
+/// The Ethereum hash of a transaction translated from Ethereum.
+fn ethereum_hash(executed: &ExecutedTransaction) -> Option<H256> {
+    executed.transaction.as_ref()?.ethereum_hash()
+}
+
+/// The sender and destinations of a transaction, each listed once.
+fn addresses(executed: &ExecutedTransaction) -> Vec<Address> {
+    let destinations = executed.transaction.iter().flat_map(|txn| {
+        txn.transaction
+            .transfers()
+            .map(|transfer| transfer.destination)
+    });
+    let mut addresses: Vec<Address> = executed.sender.into_iter().chain(destinations).collect();
+    addresses.sort();
+    addresses.dedup();
+    addresses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::Transaction;
+    use commit::Committable;
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        types::U256,
+    };
+    use sequencer_utils::u256_to_commitment;
+
+    #[async_std::test]
+    async fn test_retention() {
+        let mut rng = rand::thread_rng();
+        let alice = LocalWallet::new(&mut rng);
+        let state = State::from_initial_balances([], crate::RollupVM::new(1.into()));
+        let mut store = BlockStore::with_retention(3);
+
+        // Alice sends one transfer in each of 5 blocks
+        for height in 0..5 {
+            let transaction = Transaction {
+                amount: 1,
+                destination: Address::zero(),
+                nonce: height + 1,
+                valid_after: None,
+                valid_until: None,
+                recipients: vec![],
+                multisig: None,
+                multisig_op: None,
+            };
+            store.insert(ExecutedBlock {
+                height,
+                block: u256_to_commitment(U256::from(height)).unwrap(),
+                old_state: state.commit(),
+                new_state: state.commit(),
+                transactions: vec![ExecutedTransaction {
+                    transaction: Some(SignedTransaction::new(transaction, &alice).await),
+                    sender: Some(alice.address()),
+                    error: None,
+                }],
+                verified: false,
+            });
+        }
+
+        // Only the last 3 blocks, and their history, are retained
+        assert!(store.get(1).is_none());
+        assert_eq!(store.range(0..=4).len(), 3);
+        let page = store.history(&alice.address(), None, 2);
+        let heights: Vec<u64> = page.entries.iter().map(|entry| entry.height).collect();
+        assert_eq!(heights, [4, 3]);
+        assert_eq!(page.next_cursor, Some(3));
+        let page = store.history(&alice.address(), page.next_cursor, 2);
+        let heights: Vec<u64> = page.entries.iter().map(|entry| entry.height).collect();
+        assert_eq!(heights, [2]);
+        assert_eq!(page.next_cursor, None);
+
+        // Cursors into the pruned history return nothing
+        assert!(store
+            .history(&alice.address(), Some(1), 2)
+            .entries
+            .is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_history_pagination() {
+        let mut rng = rand::thread_rng();
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+        let charlie = LocalWallet::new(&mut rng);
+        let state = State::from_initial_balances([], crate::RollupVM::new(1.into()));
+        let mut store = BlockStore::default();
+
+        // Alice sends one transfer to Bob in each of 5 blocks, and one to Charlie in the last.
+        for height in 0..5 {
+            let mut transactions = vec![];
+            let mut destinations = vec![bob.address()];
+            if height == 4 {
+                destinations.push(charlie.address());
+            }
+            for (i, destination) in destinations.into_iter().enumerate() {
+                let transaction = Transaction {
+                    amount: 1,
+                    destination,
+                    nonce: height + i as u64 + 1,
+                    valid_after: None,
+                    valid_until: None,
+                    recipients: vec![],
+                    multisig: None,
+                    multisig_op: None,
+                };
+                transactions.push(ExecutedTransaction {
+                    transaction: Some(SignedTransaction::new(transaction, &alice).await),
+                    sender: Some(alice.address()),
+                    error: None,
+                });
+            }
+            store.insert(ExecutedBlock {
+                height,
+                block: u256_to_commitment(U256::from(height)).unwrap(),
+                old_state: state.commit(),
+                new_state: state.commit(),
+                transactions,
+                verified: false,
+            });
+        }
+
+        // Bob's history, newest first, two at a time
+        let page = store.history(&bob.address(), None, 2);
+        let heights: Vec<u64> = page.entries.iter().map(|entry| entry.height).collect();
+        assert_eq!(heights, [4, 3]);
+        let page = store.history(&bob.address(), page.next_cursor, 2);
+        let heights: Vec<u64> = page.entries.iter().map(|entry| entry.height).collect();
+        assert_eq!(heights, [2, 1]);
+        let page = store.history(&bob.address(), page.next_cursor, 2);
+        let heights: Vec<u64> = page.entries.iter().map(|entry| entry.height).collect();
+        assert_eq!(heights, [0]);
+        assert_eq!(page.next_cursor, None);
+
+        // Alice sent all 6 transactions, Charlie received one
+        assert_eq!(store.history(&alice.address(), None, 100).entries.len(), 6);
+        let page = store.history(&charlie.address(), None, 100);
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!((page.entries[0].height, page.entries[0].index), (4, 1));
+
+        // A zero limit still makes progress
+        let page = store.history(&bob.address(), None, 0);
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.next_cursor, Some(4));
+
+        // Recording a block twice does not duplicate its history entries
+        let block = store.get(4).unwrap().clone();
+        store.insert(block);
+        assert_eq!(store.history(&alice.address(), None, 100).entries.len(), 6);
+
+        // Unknown addresses have an empty history
+        assert!(store
+            .history(&Address::zero(), None, 100)
+            .entries
+            .is_empty());
+    }
+}
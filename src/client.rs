@@ -0,0 +1,320 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! A typed client for the rollup API.
+
+use ethers::abi::Address;
+use futures::{Stream, TryStreamExt};
+use snafu::Snafu;
+use std::time::Duration;
+use surf_disco::{Client, Url};
+use tide_disco::{error::ServerError, StatusCode};
+
+use crate::api::{AccountUpdate, BlockSummary, SimulationResult};
+use crate::blocks::{ExecutedBlock, HistoryEntry, HistoryPage};
+use crate::error::RollupError;
+use crate::multisig::Multisig;
+use crate::state::{Amount, Nonce};
+use crate::status::NodeStatus;
+use crate::transaction::SignedTransaction;
+
+/// An error returned by the rollup API.
+#[derive(Snafu, Clone, Debug, PartialEq)]
+pub enum ApiError {
+    /// The transaction was rejected by the rollup's validation rules.
+    #[snafu(display("Transaction rejected: {error}"))]
+    Rejected { error: RollupError },
+    /// Any other failure, including transport errors and malformed requests.
+    #[snafu(display("Rollup API error ({status}): {message}"))]
+    Server { status: StatusCode, message: String },
+}
+
+impl From<ServerError> for ApiError {
+    /// Transactions rejected by the rollup are reported with a client error status, which depends
+    /// on the kind of rejection, and the JSON serialization of the `RollupError` as the message,
+    /// which is recovered here.
+    fn from(err: ServerError) -> Self {
+        if err.status.is_client_error() {
+            if let Ok(error) = serde_json::from_str::<RollupError>(&err.message) {
+                return Self::Rejected { error };
+            }
+        }
+        Self::Server {
+            status: err.status,
+            message: err.message,
+        }
+    }
+}
+
+impl ApiError {
+    /// The `RollupError` which caused the transaction to be rejected, if any.
+    pub fn rollup_error(&self) -> Option<&RollupError> {
+        match self {
+            Self::Rejected { error } => Some(error),
+            Self::Server { .. } => None,
+        }
+    }
+}
+
+/// A client for the routes of the `rollup` API module.
+#[derive(Clone, Debug)]
+pub struct RollupApiClient {
+    client: Client<ServerError>,
+}
+
+impl RollupApiClient {
+    /// Create a client for the rollup API served at `url`.
+    pub fn new(url: Url) -> Self {
+        Self {
+            client: Client::new(url),
+        }
+    }
+
+    /// Wait until the API is reachable, or until `timeout` elapses. Returns whether the API is
+    /// reachable.
+    pub async fn connect(&self, timeout: Option<Duration>) -> bool {
+        self.client.connect(timeout).await
+    }
+
+    /// Validate a signed transaction and submit it to the sequencer, or hold it in the mempool if
+    /// its nonce is ahead of the sender's next expected nonce.
+    pub async fn submit(&self, transaction: &SignedTransaction) -> Result<(), ApiError> {
+        self.client
+            .post::<()>("rollup/submit")
+            .body_json(transaction)?
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Submit a signed transaction to the sequencer as is, bypassing validation and the mempool.
+    pub async fn submit_unchecked(&self, transaction: &SignedTransaction) -> Result<(), ApiError> {
+        self.client
+            .post::<()>("rollup/submit/true")
+            .body_json(transaction)?
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Apply a signed transaction to a snapshot of the current state and report the outcome.
+    pub async fn simulate(
+        &self,
+        transaction: &SignedTransaction,
+    ) -> Result<SimulationResult, ApiError> {
+        Ok(self
+            .client
+            .post("rollup/simulate")
+            .body_json(transaction)?
+            .send()
+            .await?)
+    }
+
+    /// Fetch the balance of an address.
+    pub async fn balance(&self, address: Address) -> Result<Amount, ApiError> {
+        Ok(self
+            .client
+            .get(&format!("rollup/balance/{address:?}"))
+            .send()
+            .await?)
+    }
+
+    /// Fetch the owners of a multisig account, or `None` if `address` is not a multisig account.
+    pub async fn multisig(&self, address: Address) -> Result<Option<Multisig>, ApiError> {
+        Ok(self
+            .client
+            .get(&format!("rollup/multisig/{address:?}"))
+            .send()
+            .await?)
+    }
+
+    /// Fetch the nonce of the last transaction applied for an address.
+    pub async fn nonce(&self, address: Address) -> Result<Nonce, ApiError> {
+        Ok(self
+            .client
+            .get(&format!("rollup/nonce/{address:?}"))
+            .send()
+            .await?)
+    }
+
+    /// Fetch the total supply of the rollup.
+    pub async fn supply(&self) -> Result<Amount, ApiError> {
+        Ok(self.client.get("rollup/supply").send().await?)
+    }
+
+    /// Fetch the transactions of an address held in the mempool.
+    pub async fn pending(&self, address: Address) -> Result<Vec<SignedTransaction>, ApiError> {
+        Ok(self
+            .client
+            .get(&format!("rollup/pending/{address:?}"))
+            .send()
+            .await?)
+    }
+
+    /// Fetch the block executed at a HotShot height, or `None` if it has not been executed yet.
+    pub async fn block(&self, height: u64) -> Result<Option<ExecutedBlock>, ApiError> {
+        match self
+            .client
+            .get(&format!("rollup/block/{height}"))
+            .send()
+            .await
+        {
+            Ok(block) => Ok(Some(block)),
+            Err(err) if err.status == StatusCode::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Fetch the executed blocks with HotShot heights from `from` to `to`, inclusive.
+    pub async fn blocks(&self, from: u64, to: u64) -> Result<Vec<ExecutedBlock>, ApiError> {
+        Ok(self
+            .client
+            .get(&format!("rollup/blocks/{from}/{to}"))
+            .send()
+            .await?)
+    }
+
+    /// Fetch a page of the transactions sent or received by an address, newest first.
+    ///
+    /// # Parameters
+    /// - `address`: The address whose transactions to list.
+    /// - `cursor`: The `next_cursor` of the previous page, or `None` to start from the newest transaction.
+    /// - `limit`: The maximum number of transactions to return, or `None` for the server default.
+    pub async fn history(
+        &self,
+        address: Address,
+        cursor: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<HistoryPage, ApiError> {
+        let mut path = format!("rollup/history/{address:?}");
+        if let Some(cursor) = cursor {
+            path += &format!("/cursor/{cursor}");
+        }
+        if let Some(limit) = limit {
+            path += &format!("/limit/{limit}");
+        }
+        Ok(self.client.get(&path).send().await?)
+    }
+
+    /// Find the outcome of the transaction sent by `sender` with `nonce`, or `None` if it has not
+    /// been executed yet.
+    ///
+    /// Anyone can have a transaction rejected with an already used nonce, or claiming a multisig
+    /// account as sender, so an applied transaction is preferred over rejected ones with the same
+    /// nonce, and a rejected one is only returned if none was applied.
+    ///
+    /// The sender's history is searched newest first, and the search stops at the first older
+    /// transaction applied for `sender`, since nonces only increase.
+    pub async fn receipt(
+        &self,
+        sender: Address,
+        nonce: Nonce,
+    ) -> Result<Option<HistoryEntry>, ApiError> {
+        let mut rejected = None;
+        let mut cursor = None;
+        loop {
+            let page = self.history(sender, cursor, None).await?;
+            for entry in page.entries {
+                let executed = &entry.transaction;
+                let Some(transaction) = &executed.transaction else {
+                    continue;
+                };
+                if executed.sender != Some(sender) {
+                    continue;
+                }
+                let applied = executed.error.is_none();
+                if transaction.transaction.nonce == nonce {
+                    if applied {
+                        return Ok(Some(entry));
+                    }
+                    rejected.get_or_insert(entry);
+                } else if transaction.transaction.nonce < nonce && applied {
+                    return Ok(rejected);
+                }
+            }
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(rejected),
+            }
+        }
+    }
+
+    /// Subscribe to a summary of each block executed by the rollup.
+    pub async fn stream_blocks(
+        &self,
+    ) -> Result<impl Stream<Item = Result<BlockSummary, ApiError>>, ApiError> {
+        let blocks = self
+            .client
+            .socket("rollup/stream/blocks")
+            .subscribe::<BlockSummary>()
+            .await?;
+        Ok(blocks.map_err(ApiError::from))
+    }
+
+    /// Subscribe to the balance and nonce of an address, starting with the current values.
+    pub async fn stream_account(
+        &self,
+        address: Address,
+    ) -> Result<impl Stream<Item = Result<AccountUpdate, ApiError>>, ApiError> {
+        let updates = self
+            .client
+            .socket(&format!("rollup/stream/account/{address:?}"))
+            .subscribe::<AccountUpdate>()
+            .await?;
+        Ok(updates.map_err(ApiError::from))
+    }
+
+    /// Fetch the status of the rollup node.
+    pub async fn status(&self) -> Result<NodeStatus, ApiError> {
+        Ok(self.client.get("rollup/status").send().await?)
+    }
+
+    /// Check whether the node can reach its dependencies and has caught up with HotShot.
+    pub async fn ready(&self) -> Result<bool, ApiError> {
+        match self
+            .client
+            .get::<NodeStatus>("rollup/status/ready")
+            .send()
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(err) if err.status == StatusCode::ServiceUnavailable => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_mapping() {
+        let rollup_error = RollupError::InvalidNonce {
+            address: Address::zero(),
+            expected: 2,
+            actual: 1,
+        };
+        let err = ServerError {
+            status: StatusCode::BadRequest,
+            message: serde_json::to_string(&rollup_error).unwrap(),
+        };
+        assert_eq!(ApiError::from(err).rollup_error(), Some(&rollup_error));
+
+        // Other errors are passed through
+        let err = ServerError {
+            status: StatusCode::BadRequest,
+            message: "Malformed address.".into(),
+        };
+        assert_eq!(
+            ApiError::from(err),
+            ApiError::Server {
+                status: StatusCode::BadRequest,
+                message: "Malformed address.".into(),
+            }
+        );
+    }
+}
@@ -8,8 +8,13 @@ use crate::state::Nonce;
 use ethers::abi::Address;
 use serde::{Deserialize, Serialize};
 use snafu::Snafu;
+use strum_macros::IntoStaticStr;
 
-#[derive(Snafu, Debug, Eq, PartialEq, Serialize, Deserialize)]
+/// An error applying a transaction to the rollup state.
+///
+/// Each variant has a stable numeric code, returned by [`RollupError::code`], which clients can
+/// rely on across releases. Codes are never reused or renumbered; new variants get new codes.
+#[derive(Snafu, Clone, Debug, Eq, PartialEq, Serialize, Deserialize, IntoStaticStr)]
 pub enum RollupError {
     #[snafu(display("Error validating the transaction signature."))]
     SignatureError,
@@ -21,4 +26,132 @@ pub enum RollupError {
         expected: Nonce,
         actual: Nonce,
     },
+    #[snafu(display(
+        "Transaction is not valid at block height {height}. Valid after {valid_after:?}, valid until {valid_until:?}"
+    ))]
+    OutsideValidityWindow {
+        height: u64,
+        valid_after: Option<u64>,
+        valid_until: Option<u64>,
+    },
+    #[snafu(display("Invalid Ethereum transaction: {reason}"))]
+    InvalidEthereumTransaction { reason: String },
+    #[snafu(display("Arithmetic overflow in the balance or nonce of {address}."))]
+    Overflow { address: Address },
+    #[snafu(display("Sender {address} has no account."))]
+    UnknownAccount { address: Address },
+    #[snafu(display("Namespace leaf does not decode to a rollup transaction."))]
+    MalformedTransaction,
+    #[snafu(display("Account {address} is not a multisig account."))]
+    NotMultisig { address: Address },
+    #[snafu(display(
+        "Multisig account {address} requires {threshold} owner signatures, found {signers}."
+    ))]
+    InsufficientSignatures {
+        address: Address,
+        threshold: u32,
+        signers: u32,
+    },
+    #[snafu(display("Invalid multisig owners: {reason}"))]
+    InvalidMultisigOwners { reason: String },
+    #[snafu(display("Account {address} already exists."))]
+    AccountExists { address: Address },
+    #[snafu(display(
+        "Ethereum transaction is signed for chain ID {actual:?}, expected {expected}."
+    ))]
+    WrongChainId {
+        expected: u64,
+        /// `None` for legacy transactions signed without a chain ID.
+        actual: Option<u64>,
+    },
+    #[snafu(display("Transaction makes {actual} transfers, at most {max} are allowed."))]
+    TooManyRecipients { max: usize, actual: usize },
+}
+
+impl RollupError {
+    /// The stable numeric code of this error.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::SignatureError => 1,
+            Self::InsufficientBalance { .. } => 2,
+            Self::InvalidNonce { .. } => 3,
+            Self::OutsideValidityWindow { .. } => 4,
+            Self::InvalidEthereumTransaction { .. } => 5,
+            Self::Overflow { .. } => 6,
+            Self::UnknownAccount { .. } => 7,
+            Self::MalformedTransaction => 8,
+            Self::NotMultisig { .. } => 9,
+            Self::InsufficientSignatures { .. } => 10,
+            Self::InvalidMultisigOwners { .. } => 11,
+            Self::AccountExists { .. } => 12,
+            Self::WrongChainId { .. } => 13,
+            Self::TooManyRecipients { .. } => 14,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_are_stable() {
+        let address = Address::zero();
+        let errors = [
+            (RollupError::SignatureError, 1),
+            (RollupError::InsufficientBalance { address }, 2),
+            (
+                RollupError::InvalidNonce {
+                    address,
+                    expected: 1,
+                    actual: 0,
+                },
+                3,
+            ),
+            (
+                RollupError::OutsideValidityWindow {
+                    height: 0,
+                    valid_after: None,
+                    valid_until: None,
+                },
+                4,
+            ),
+            (
+                RollupError::InvalidEthereumTransaction {
+                    reason: String::new(),
+                },
+                5,
+            ),
+            (RollupError::Overflow { address }, 6),
+            (RollupError::UnknownAccount { address }, 7),
+            (RollupError::MalformedTransaction, 8),
+            (RollupError::NotMultisig { address }, 9),
+            (
+                RollupError::InsufficientSignatures {
+                    address,
+                    threshold: 2,
+                    signers: 1,
+                },
+                10,
+            ),
+            (
+                RollupError::InvalidMultisigOwners {
+                    reason: String::new(),
+                },
+                11,
+            ),
+            (RollupError::AccountExists { address }, 12),
+            (
+                RollupError::WrongChainId {
+                    expected: 1,
+                    actual: None,
+                },
+                13,
+            ),
+            (RollupError::TooManyRecipients { max: 1, actual: 2 }, 14),
+        ];
+        for (err, code) in errors {
+            assert_eq!(err.code(), code, "{err:?}");
+        }
+    }
 }
@@ -4,23 +4,27 @@
 // You should have received a copy of the MIT License
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
+use crate::blocks::BlockStore;
+use crate::metrics::metrics;
 use crate::prover::BatchProof;
 use async_compatibility_layer::async_primitives::broadcast::BroadcastSender;
 use async_std::sync::{Arc, RwLock};
 use async_std::task::sleep;
 use commit::Committable;
-use contract_bindings::example_rollup::{self, ExampleRollup};
+use contract_bindings::example_rollup;
 use ethers::prelude::*;
-use hotshot_contract_bindings::hot_shot::{HotShot, NewBlocksFilter};
-use sequencer::{api::endpoints::NamespaceProofQueryData, Header, Vm};
+use futures::{stream::BoxStream, StreamExt};
+use sequencer::{Header, Vm};
+use std::time::Duration;
 use surf_disco::Url;
 
-use sequencer_utils::{commitment_to_u256, connect_rpc, contract_send, u256_to_commitment};
+use sequencer_utils::{commitment_to_u256, connect_rpc, u256_to_commitment};
 
+use crate::invariants::InvariantChecker;
+use crate::l1::{ContractL1, NewBlocks, RollupL1};
+use crate::query::{HotShotClient, HotShotQuery, QueryError};
 use crate::state::State;
 
-type HotShotClient = surf_disco::Client<hotshot_query_service::Error>;
-
 #[derive(Clone, Debug)]
 pub struct ExecutorOptions {
     pub sequencer_url: Url,
@@ -31,11 +35,21 @@ pub struct ExecutorOptions {
     pub hotshot_address: Address,
     pub rollup_address: Address,
     pub output_stream: Option<BroadcastSender<(u64, State)>>,
+    pub block_store: Arc<RwLock<BlockStore>>,
+    /// Check the invariants of the state machine after each block.
+    pub check_invariants: bool,
 }
 
+/// How long to wait before retrying a failed L1 or HotShot request.
+#[cfg(not(test))]
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+#[cfg(test)]
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
 /// Runs the executor service, which is responsible for:
 /// 1) Fetching blocks of ordered transactions from HotShot and applying them to the Rollup State.
 /// 2) Submitting mock proofs to the Rollup Contract.
+/// 3) Recording each executed block, and whether it has been verified on L1, in the block store.
 pub async fn run_executor(opt: &ExecutorOptions, state: Arc<RwLock<State>>) {
     let ExecutorOptions {
         rollup_account_index,
@@ -46,6 +60,8 @@ pub async fn run_executor(opt: &ExecutorOptions, state: Arc<RwLock<State>>) {
         rollup_address,
         rollup_mnemonic,
         output_stream,
+        block_store,
+        check_invariants,
     } = opt;
 
     // Build the URL to query the availability of blocks from HotShot
@@ -71,58 +87,104 @@ pub async fn run_executor(opt: &ExecutorOptions, state: Arc<RwLock<State>>) {
         .expect("Unable to make websocket connection to L1");
 
     // Initialize the Rollup and HotShot contracts
-    let rollup_contract = ExampleRollup::new(*rollup_address, Arc::new(l1));
-    let hotshot_contract = HotShot::new(*hotshot_address, Arc::new(socket_provider));
-
-    // Create a filter to listen to new block events from HotShot
-    let filter = hotshot_contract
-        .new_blocks_filter()
-        .from_block(0)
-        // Ethers does not set the contract address on filters created via contract bindings. This
-        // seems like a bug and I have reported it: https://github.com/gakonst/ethers-rs/issues/2528.
-        // In the mean time we can work around by setting the address manually.
-        .address(hotshot_contract.address().into());
+    let l1 = ContractL1::new(socket_provider, *hotshot_address, *rollup_address, l1);
+
+    run_executor_with(
+        &l1,
+        &hotshot,
+        state,
+        output_stream.clone(),
+        block_store.clone(),
+        *check_invariants,
+    )
+    .await
+}
 
+/// Runs the executor service against any implementation of the L1 contracts and of the HotShot
+/// query service.
+///
+/// Failed reads of block commitments and namespace proofs, and failed proof submissions, are
+/// retried until they succeed. If the HotShot header stream fails, it is resubscribed from the next
+/// header the executor needs. If `check_invariants` is set, the executor panics as soon as a block
+/// violates an invariant of the state machine.
+pub async fn run_executor_with(
+    l1: &impl RollupL1,
+    hotshot: &impl HotShotQuery,
+    state: Arc<RwLock<State>>,
+    output_stream: Option<BroadcastSender<(u64, State)>>,
+    block_store: Arc<RwLock<BlockStore>>,
+    check_invariants: bool,
+) {
     // Subscribe to the block events stream
-    let mut commits_stream = filter
-        .subscribe()
+    let mut commits_stream = l1
+        .new_blocks()
         .await
         .expect("Unable to subscribe to L1 log stream");
 
     // Subscribe to the HotShot block header stream
-    let mut header_stream = hotshot
-        .socket("stream/headers/0")
-        .subscribe::<Header>()
-        .await
-        .expect("Unable to subscribe to HotShot block header stream");
+    let mut next_header = 0;
+    let mut header_stream = subscribe_headers(hotshot, next_header).await;
 
     // Get the VM ID of the Rollup
     let vm_id: u64 = state.read().await.vm.id().into();
 
+    let mut invariants = if check_invariants {
+        Some(InvariantChecker::new(&*state.read().await))
+    } else {
+        None
+    };
+
     // Main loop: process each new block event
     while let Some(event) = commits_stream.next().await {
         // Extract block number and number of blocks from the event
-        let (first_block, num_blocks) = match event {
-            Ok(NewBlocksFilter {
-                first_block_number,
-                num_blocks,
-            }) => (first_block_number, num_blocks.as_u64()),
+        let NewBlocks {
+            first_block,
+            num_blocks,
+        } = match event {
+            Ok(event) => event,
             Err(err) => {
                 tracing::error!("Error in HotShot block stream, retrying: {err}");
                 continue;
             }
         };
 
+        let proof_timer = metrics().proof_seconds.start_timer();
+        let committed_blocks = first_block + num_blocks;
+
         // Full block content may not be available immediately so wait for all blocks to be ready
         // before building the batch proof
 
         // Collect the block headers corresponding to the number of blocks received
-        let headers: Vec<Header> = header_stream
-            .by_ref()
-            .take(num_blocks as usize)
-            .map(|result| result.expect("Error fetching block header"))
-            .collect()
-            .await;
+        let mut headers: Vec<Header> = vec![];
+        while headers.len() < num_blocks as usize {
+            match header_stream.next().await {
+                Some(Ok(header)) => {
+                    headers.push(header);
+                    next_header += 1;
+                }
+                Some(Err(err)) => {
+                    tracing::warn!("Error in HotShot header stream, resubscribing: {err}");
+                    sleep(RETRY_INTERVAL).await;
+                    header_stream = subscribe_headers(hotshot, next_header).await;
+                }
+                None => {
+                    tracing::warn!("HotShot header stream ended, resubscribing");
+                    sleep(RETRY_INTERVAL).await;
+                    header_stream = subscribe_headers(hotshot, next_header).await;
+                }
+            }
+        }
+
+        // The HotShot contract may already be ahead of this batch if the executor is behind, so the
+        // lag is measured from the latest committed block. It is only a metric, so a failed read
+        // falls back to the end of the batch.
+        let hotshot_height = match l1.block_height().await {
+            Ok(height) => height.max(committed_blocks),
+            Err(err) => {
+                tracing::warn!("Unable to read the HotShot block height: {err}");
+                committed_blocks
+            }
+        };
 
         // Execute new blocks, generating proofs.
         let mut proofs = vec![];
@@ -134,12 +196,18 @@ pub async fn run_executor(opt: &ExecutorOptions, state: Arc<RwLock<State>>) {
         );
         // Process each block in the batch, applying transactions to the rollup state
         for (i, header) in headers.into_iter().enumerate() {
+            let block_height = first_block + (i as u64);
+
             // Fetch the commitment from the HotShot contract for the block
-            let commitment = hotshot_contract
-                .commitments(first_block + i)
-                .call()
-                .await
-                .expect("Unable to read commitment");
+            let commitment = loop {
+                match l1.commitment(block_height).await {
+                    Ok(commitment) => break commitment,
+                    Err(err) => {
+                        tracing::warn!("Unable to read commitment, retrying: {err}");
+                        sleep(RETRY_INTERVAL).await;
+                    }
+                }
+            };
 
             // Deserialize the commitment into a usable format
             let block_commitment =
@@ -150,31 +218,39 @@ pub async fn run_executor(opt: &ExecutorOptions, state: Arc<RwLock<State>>) {
                 panic!("Block commitment does not match hash of received block, the executor cannot continue");
             }
             // Fetch the namespace proof for the transactions within the block
-            let namespace_proof_query: NamespaceProofQueryData = hotshot
-                .get(&format!(
-                    "block/{}/namespace/{}",
-                    first_block.as_u64() + (i as u64),
-                    vm_id
-                ))
-                .send()
-                .await
-                .unwrap();
-            let namespace_proof = namespace_proof_query.proof;
+            let namespace_proof = loop {
+                match hotshot.namespace_proof(block_height, vm_id).await {
+                    Ok(proof) => break proof,
+                    Err(err) => {
+                        tracing::warn!("Unable to fetch namespace proof, retrying: {err}");
+                        sleep(RETRY_INTERVAL).await;
+                    }
+                }
+            };
 
             // Apply the block's transactions to the current rollup state
             let mut state = state.write().await;
-            proofs.push(
-                state
-                    .execute_block(header.transactions_root, namespace_proof)
-                    .await,
-            );
+            let execution_timer = metrics().block_execution_seconds.start_timer();
+            let (proof, executed_block) = state
+                .execute_block(block_height, header.transactions_root, namespace_proof)
+                .await;
+            execution_timer.observe_duration();
+            if let Some(invariants) = &mut invariants {
+                if let Err(err) = invariants.check_block(&executed_block, &state) {
+                    panic!("State invariant violated, the executor cannot continue: {err}");
+                }
+            }
+            proofs.push(proof);
+            block_store.write().await.insert(executed_block);
+            metrics().blocks_executed.inc();
+            metrics()
+                .hotshot_lag
+                .set((hotshot_height - block_height - 1) as i64);
+            metrics().l1_lag.inc();
 
             // Optionally send the updated state through an output stream for other services
             if let Some(stream) = &output_stream {
-                stream
-                    .send_async((first_block.as_u64() + (i as u64), state.clone()))
-                    .await
-                    .ok();
+                stream.send_async((block_height, state.clone())).await.ok();
             }
         }
 
@@ -195,11 +271,45 @@ pub async fn run_executor(opt: &ExecutorOptions, state: Arc<RwLock<State>>) {
         let proof = example_rollup::BatchProof::from(proof);
 
         // Attempt to send the batch proof to the Rollup Contract on L1
-        let call = rollup_contract.verify_blocks(num_blocks, state_comm, proof);
         // Retry sending the proof if there is a failure, with a delay
-        while let Err(err) = contract_send(&call).await {
-            tracing::warn!("Failed to submit proof to contract, retrying: {err}");
-            sleep(std::time::Duration::from_secs(1)).await;
+        let gas_used = loop {
+            match l1
+                .verify_blocks(num_blocks, state_comm, proof.clone())
+                .await
+            {
+                Ok(gas_used) => break gas_used,
+                Err(err) => {
+                    tracing::warn!("Failed to submit proof to contract, retrying: {err}");
+                    metrics().verify_retries.inc();
+                    sleep(RETRY_INTERVAL).await;
+                }
+            }
+        };
+        if let Some(gas_used) = gas_used {
+            metrics().verify_gas_used.inc_by(gas_used.as_u64());
+        }
+        proof_timer.observe_duration();
+        metrics().l1_lag.sub(num_blocks as i64);
+        block_store
+            .write()
+            .await
+            .mark_verified(first_block..=first_block + num_blocks - 1);
+    }
+}
+
+/// Subscribes to the HotShot block header stream starting from the block at height `from`,
+/// retrying until the subscription succeeds.
+async fn subscribe_headers(
+    hotshot: &impl HotShotQuery,
+    from: u64,
+) -> BoxStream<'_, Result<Header, QueryError>> {
+    loop {
+        match hotshot.headers(from).await {
+            Ok(headers) => break headers,
+            Err(err) => {
+                tracing::warn!("Unable to subscribe to HotShot block headers, retrying: {err}");
+                sleep(RETRY_INTERVAL).await;
+            }
         }
     }
 }
@@ -207,6 +317,7 @@ pub async fn run_executor(opt: &ExecutorOptions, state: Arc<RwLock<State>>) {
 #[cfg(test)]
 mod test {
     use crate::state::{Amount, Nonce};
+    use crate::testing::{mock_block, MockHotShot, MockL1};
     use crate::transaction::{SignedTransaction, Transaction};
     use crate::utils::{create_provider, deploy_example_contract, ExampleRollupContract};
     use crate::RollupVM;
@@ -401,6 +512,11 @@ mod test {
                 amount,
                 destination: self.bob.address(),
                 nonce,
+                valid_after: None,
+                valid_until: None,
+                recipients: vec![],
+                multisig: None,
+                multisig_op: None,
             };
             let txn = SignedTransaction::new(txn, &self.alice).await;
             self.vm.wrap(&txn)
@@ -504,6 +620,7 @@ mod test {
             delay: None,
         };
 
+        let block_store = Arc::new(RwLock::new(BlockStore::default()));
         let rollup_opt = ExecutorOptions {
             sequencer_url,
             rollup_account_index: test_l1.clients.funded[1].index,
@@ -513,6 +630,8 @@ mod test {
             hotshot_address: test_l1.hotshot.address(),
             rollup_address: test_rollup.contract.address(),
             output_stream: Some(test_rollup.executor_send.clone()),
+            block_store: block_store.clone(),
+            check_invariants: true,
         };
 
         let state_lock = test_rollup.state.clone();
@@ -527,6 +646,16 @@ mod test {
                 bob_balance == 100
             })
             .await;
+
+        // The executed transaction is recorded in the block store
+        let block_store = block_store.read().await;
+        let executed = block_store
+            .range(0..=u64::MAX)
+            .into_iter()
+            .flat_map(|block| block.transactions)
+            .find(|txn| txn.sender == Some(test_rollup.alice.address()))
+            .expect("Executed transaction should be recorded");
+        assert!(executed.error.is_none());
     }
 
     #[async_std::test]
@@ -609,6 +738,8 @@ mod test {
                 hotshot_address: test_l1.hotshot.address(),
                 rollup_address: test_rollup.contract.address(),
                 output_stream: Some(test_rollup.executor_send.clone()),
+                block_store: Default::default(),
+                check_invariants: true,
             };
             spawn(async move { run_executor(&rollup_opt, state_lock).await });
         }
@@ -683,6 +814,8 @@ mod test {
             hotshot_address: test_l1.hotshot.address(),
             rollup_address: test_rollup.contract.address(),
             output_stream: Some(test_rollup.executor_send.clone()),
+            block_store: Default::default(),
+            check_invariants: true,
         };
 
         let state_lock = test_rollup.state.clone();
@@ -711,4 +844,113 @@ mod test {
             })
             .await;
     }
+
+    /// Execute blocks, each transferring 10 tokens from Alice to Bob, against in-memory fakes of L1
+    /// and HotShot. The test is driven by `before_commit`, which may inject faults, and returns
+    /// the fakes once all blocks have been verified, along with the executor's state.
+    async fn execute_mock_blocks(
+        num_blocks: u64,
+        before_commit: impl FnOnce(&MockL1, &MockHotShot),
+    ) -> (MockL1, Arc<RwLock<State>>, Wallet<SigningKey>) {
+        let alice = LocalWallet::new(&mut ChaChaRng::seed_from_u64(0));
+        let bob = LocalWallet::new(&mut ChaChaRng::seed_from_u64(1));
+        let vm = RollupVM::new(1.into());
+        let state = Arc::new(RwLock::new(State::from_initial_balances(
+            [(alice.address(), 9999)],
+            vm,
+        )));
+        let l1 = MockL1::default();
+        let hotshot = MockHotShot::default();
+
+        let mut blocks = vec![];
+        for nonce in 1..=num_blocks {
+            let txn = Transaction {
+                amount: 10,
+                destination: bob.address(),
+                nonce,
+                valid_after: None,
+                valid_until: None,
+                recipients: vec![],
+                multisig: None,
+                multisig_op: None,
+            };
+            let txn = vm.wrap(&SignedTransaction::new(txn, &alice).await);
+            blocks.push(mock_block(&[txn], vm.id()));
+        }
+        let headers: Vec<Header> = blocks.iter().map(|(header, _)| header.clone()).collect();
+
+        let executor_l1 = l1.clone();
+        let executor_hotshot = hotshot.clone();
+        let executor_state = state.clone();
+        spawn(async move {
+            run_executor_with(
+                &executor_l1,
+                &executor_hotshot,
+                executor_state,
+                None,
+                Default::default(),
+                true,
+            )
+            .await
+        });
+
+        before_commit(&l1, &hotshot);
+        for (header, proof) in blocks {
+            hotshot.push_block(header, proof);
+        }
+        // Commit the blocks in two batches
+        let (first, second) = headers.split_at(headers.len() / 2);
+        l1.commit_blocks(first);
+        l1.commit_blocks(second);
+
+        async_std::future::timeout(Duration::from_secs(10), async {
+            while l1.verified_blocks() < num_blocks {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Blocks were not verified in time");
+
+        (l1, state, bob)
+    }
+
+    #[async_std::test]
+    async fn test_execute_mock() {
+        setup_logging();
+        setup_backtrace();
+
+        let (l1, state, bob) = execute_mock_blocks(4, |_, _| {}).await;
+        let state = state.read().await;
+        assert_eq!(state.get_balance(&bob.address()), 40);
+        assert_eq!(
+            l1.state_commitment(),
+            Some(commitment_to_u256(state.commit()))
+        );
+    }
+
+    #[async_std::test]
+    async fn test_execute_mock_faults() {
+        setup_logging();
+        setup_backtrace();
+
+        let retries = metrics().verify_retries.get();
+        let (l1, state, bob) = execute_mock_blocks(4, |l1, hotshot| {
+            l1.inject_event_error();
+            l1.fail_commitment_reads(2);
+            l1.fail_verifications(2);
+            hotshot.fail_proof_queries(2);
+            hotshot.fail_headers(2);
+        })
+        .await;
+
+        // The executor retries until every request succeeds, ending in the same state as without
+        // faults
+        let state = state.read().await;
+        assert_eq!(state.get_balance(&bob.address()), 40);
+        assert_eq!(
+            l1.state_commitment(),
+            Some(commitment_to_u256(state.commit()))
+        );
+        assert!(metrics().verify_retries.get() >= retries + 2);
+    }
 }
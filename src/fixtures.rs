@@ -0,0 +1,490 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Recording and replaying the HotShot blocks executed by the rollup.
+//!
+//! A fixture holds the genesis of a rollup, the header and namespace proof of each block the
+//! executor fetched from HotShot in a range of heights, and the state commitments the rollup
+//! contract accepted for those blocks. Replaying a fixture executes the blocks, offline, and checks
+//! the resulting commitments against the recorded ones.
+//!
+//! A fixture starting at block `H > 0` also holds a snapshot of the rollup state before block `H`,
+//! from which replay starts. Recording still executes the blocks before `H` to take the snapshot,
+//! but they are not saved, and replay does not execute them again.
+
+use commit::Committable;
+use contract_bindings::example_rollup::{ExampleRollup, StateUpdateFilter};
+use ethers::prelude::*;
+use futures::StreamExt;
+use sequencer::{Header, NMTRoot, NamespaceProofType};
+use sequencer_utils::commitment_to_u256;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{fs, io, path::Path};
+
+use crate::genesis::Genesis;
+use crate::query::{HotShotQuery, QueryError};
+use crate::state::{State, StateSnapshot};
+
+/// An error recording or replaying fixtures.
+#[derive(Debug, Snafu)]
+pub enum FixtureError {
+    #[snafu(display("Error reading or writing fixtures: {source}"))]
+    Io { source: io::Error },
+    #[snafu(display("Malformed fixtures: {source}"))]
+    Json { source: serde_json::Error },
+    #[snafu(display("Error fetching from HotShot: {message}"))]
+    HotShot { message: String },
+    #[snafu(display("Error reading rollup contract events: {message}"))]
+    L1 { message: String },
+    #[snafu(display(
+        "Fixtures must contain consecutive blocks from the start height. Expected block {expected}, found {actual}"
+    ))]
+    Gap { expected: u64, actual: u64 },
+    #[snafu(display("Fixtures starting at block {start} must include a snapshot of the state"))]
+    MissingSnapshot { start: u64 },
+    #[snafu(display(
+        "State commitment after {block_height} blocks is {actual}, but {expected} was recorded"
+    ))]
+    CommitmentMismatch {
+        block_height: u64,
+        expected: U256,
+        actual: U256,
+    },
+}
+
+/// The data fetched by the executor for a single HotShot block.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BlockFixture {
+    pub height: u64,
+    pub header: Header,
+    pub proof: NamespaceProofType,
+}
+
+/// A state commitment accepted by the rollup contract, after `block_height` blocks.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StateUpdate {
+    pub block_height: u64,
+    pub state_commitment: U256,
+}
+
+impl From<StateUpdateFilter> for StateUpdate {
+    fn from(event: StateUpdateFilter) -> Self {
+        Self {
+            block_height: event.block_height.as_u64(),
+            state_commitment: event.state_commitment,
+        }
+    }
+}
+
+/// The outcome of a successful replay.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// The number of blocks executed.
+    pub blocks: u64,
+    /// The number of recorded state commitments checked.
+    pub state_updates: usize,
+    /// The commitment of the state after the last block.
+    pub state_commitment: U256,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Fixtures {
+    pub genesis: Genesis,
+    /// The height of the first recorded block.
+    #[serde(default)]
+    pub start: u64,
+    /// The state before the first recorded block, if it is not the genesis state.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<StateSnapshot>,
+    pub blocks: Vec<BlockFixture>,
+    pub state_updates: Vec<StateUpdate>,
+}
+
+impl Fixtures {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, FixtureError> {
+        let bytes = fs::read(path).context(IoSnafu)?;
+        serde_json::from_slice(&bytes).context(JsonSnafu)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), FixtureError> {
+        let bytes = serde_json::to_vec(self).context(JsonSnafu)?;
+        fs::write(path, bytes).context(IoSnafu)
+    }
+
+    /// Record the blocks executed by a rollup, and the state commitments accepted by its contract.
+    ///
+    /// # Parameters
+    /// - `hotshot`: The availability API of the HotShot query service.
+    /// - `rollup_contract`: The rollup contract whose `StateUpdate` events are recorded.
+    /// - `genesis`: The genesis of the rollup.
+    /// - `start`: The height of the first block to record.
+    /// - `num_blocks`: The number of blocks to record, or `None` for every block verified by the contract.
+    pub async fn record<M: Middleware + 'static>(
+        hotshot: &impl HotShotQuery,
+        rollup_contract: &ExampleRollup<M>,
+        genesis: Genesis,
+        start: u64,
+        num_blocks: Option<u64>,
+    ) -> Result<Self, FixtureError> {
+        let state_updates: Vec<StateUpdate> = rollup_contract
+            .state_update_filter()
+            .from_block(0)
+            // Ethers does not set the contract address on filters created via contract bindings.
+            .address(rollup_contract.address().into())
+            .query()
+            .await
+            .map_err(|err| FixtureError::L1 {
+                message: err.to_string(),
+            })?
+            .into_iter()
+            .map(StateUpdate::from)
+            .collect();
+        Self::record_blocks(hotshot, genesis, state_updates, start, num_blocks).await
+    }
+
+    /// Record `num_blocks` blocks from `start`, or every block up to the last of `state_updates`.
+    ///
+    /// The blocks before `start` are executed from the genesis state to take the snapshot.
+    pub(crate) async fn record_blocks(
+        hotshot: &impl HotShotQuery,
+        genesis: Genesis,
+        state_updates: Vec<StateUpdate>,
+        start: u64,
+        num_blocks: Option<u64>,
+    ) -> Result<Self, FixtureError> {
+        let end = match num_blocks {
+            Some(num_blocks) => start + num_blocks,
+            None => state_updates
+                .iter()
+                .map(|update| update.block_height)
+                .max()
+                .unwrap_or(0)
+                .max(start),
+        };
+
+        let hotshot_error = |err: QueryError| FixtureError::HotShot {
+            message: err.to_string(),
+        };
+        let mut headers = hotshot.headers(0).await.map_err(hotshot_error)?;
+        let mut state = genesis.state();
+        let mut blocks = vec![];
+        for height in 0..end {
+            let header = headers
+                .next()
+                .await
+                .ok_or_else(|| FixtureError::HotShot {
+                    message: "header stream ended".into(),
+                })?
+                .map_err(hotshot_error)?;
+            let proof = hotshot
+                .namespace_proof(height, genesis.vm_id)
+                .await
+                .map_err(hotshot_error)?;
+            if height < start {
+                state
+                    .execute_block(height, header.transactions_root, proof)
+                    .await;
+            } else {
+                blocks.push(BlockFixture {
+                    height,
+                    header,
+                    proof,
+                });
+            }
+        }
+
+        Ok(Self {
+            genesis,
+            start,
+            snapshot: (start > 0).then(|| state.snapshot()),
+            blocks,
+            state_updates: state_updates
+                .into_iter()
+                .filter(|update| (start..=end).contains(&update.block_height))
+                .collect(),
+        })
+    }
+
+    /// Execute the recorded blocks from the genesis state, or from the snapshot if the fixtures
+    /// start after the first block, checking the state commitment against each recorded state
+    /// update.
+    pub async fn replay(&self) -> Result<ReplayReport, FixtureError> {
+        let state = match (&self.snapshot, self.start) {
+            (_, 0) => self.genesis.state(),
+            (Some(snapshot), _) => self.genesis.restore(snapshot.clone()),
+            (None, start) => return Err(FixtureError::MissingSnapshot { start }),
+        };
+        let blocks = self.blocks.iter().map(|block| {
+            (
+                block.height,
+                block.header.transactions_root.clone(),
+                block.proof.clone(),
+            )
+        });
+        replay_blocks(state, self.start, blocks, &self.state_updates).await
+    }
+}
+
+/// Execute `blocks`, consecutive from height `start`, from `state`, the state after the blocks
+/// before `start`. The state commitment is checked against each of `state_updates`, including one
+/// for `state` itself.
+pub(crate) async fn replay_blocks(
+    mut state: State,
+    start: u64,
+    blocks: impl IntoIterator<Item = (u64, NMTRoot, NamespaceProofType)>,
+    state_updates: &[StateUpdate],
+) -> Result<ReplayReport, FixtureError> {
+    let check = |state: &State, executed: u64| {
+        let actual = commitment_to_u256(state.commit());
+        let mut checked = 0;
+        for update in state_updates
+            .iter()
+            .filter(|update| update.block_height == executed)
+        {
+            if update.state_commitment != actual {
+                return Err(FixtureError::CommitmentMismatch {
+                    block_height: executed,
+                    expected: update.state_commitment,
+                    actual,
+                });
+            }
+            checked += 1;
+        }
+        Ok(checked)
+    };
+
+    let mut executed = start;
+    let mut checked = check(&state, executed)?;
+    for (height, nmt_root, namespace_proof) in blocks {
+        if height != executed {
+            return Err(FixtureError::Gap {
+                expected: executed,
+                actual: height,
+            });
+        }
+        state.execute_block(height, nmt_root, namespace_proof).await;
+        executed += 1;
+        checked += check(&state, executed)?;
+    }
+
+    Ok(ReplayReport {
+        blocks: executed - start,
+        state_updates: checked,
+        state_commitment: commitment_to_u256(state.commit()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{mock_block, MockHotShot};
+    use crate::transaction::{SignedTransaction, Transaction};
+    use ethers::signers::LocalWallet;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use sequencer::Vm;
+
+    /// Blocks each transferring 10 tokens from the first preseeded identity, with the state
+    /// commitments after each block.
+    async fn mock_blocks(
+        genesis: &Genesis,
+        num_blocks: u64,
+    ) -> (Vec<BlockFixture>, Vec<StateUpdate>) {
+        let sender = LocalWallet::new(&mut ChaChaRng::seed_from_u64(0));
+        let vm = genesis.vm();
+        let mut state = genesis.state();
+        let mut blocks = vec![];
+        let mut state_updates = vec![];
+        for height in 0..num_blocks {
+            let txn = Transaction {
+                amount: 10,
+                destination: Address::repeat_byte(1),
+                nonce: height + 1,
+                valid_after: None,
+                valid_until: None,
+                recipients: vec![],
+                multisig: None,
+                multisig_op: None,
+            };
+            let txn = vm.wrap(&SignedTransaction::new(txn, &sender).await);
+            let (header, proof) = mock_block(&[txn], vm.id());
+            state
+                .execute_block(height, header.transactions_root.clone(), proof.clone())
+                .await;
+            blocks.push(BlockFixture {
+                height,
+                header,
+                proof,
+            });
+            state_updates.push(StateUpdate {
+                block_height: height + 1,
+                state_commitment: commitment_to_u256(state.commit()),
+            });
+        }
+        (blocks, state_updates)
+    }
+
+    fn replay_input(
+        blocks: &[BlockFixture],
+    ) -> impl Iterator<Item = (u64, NMTRoot, NamespaceProofType)> + '_ {
+        blocks.iter().map(|block| {
+            (
+                block.height,
+                block.header.transactions_root.clone(),
+                block.proof.clone(),
+            )
+        })
+    }
+
+    #[async_std::test]
+    async fn test_replay() {
+        let genesis = Genesis::default();
+        let (blocks, state_updates) = mock_blocks(&genesis, 3).await;
+
+        let report = replay_blocks(genesis.state(), 0, replay_input(&blocks), &state_updates)
+            .await
+            .unwrap();
+        assert_eq!(report.blocks, 3);
+        assert_eq!(report.state_updates, 3);
+        assert_eq!(
+            report.state_commitment,
+            state_updates.last().unwrap().state_commitment
+        );
+
+        // A diverging commitment is reported
+        let mut wrong_updates = state_updates.clone();
+        wrong_updates[1].state_commitment = U256::zero();
+        let err = replay_blocks(genesis.state(), 0, replay_input(&blocks), &wrong_updates)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            FixtureError::CommitmentMismatch {
+                block_height: 2,
+                ..
+            }
+        ));
+
+        // So is a missing block
+        let err = replay_blocks(
+            genesis.state(),
+            0,
+            replay_input(&blocks).skip(1),
+            &state_updates,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            FixtureError::Gap {
+                expected: 0,
+                actual: 1
+            }
+        ));
+    }
+
+    #[async_std::test]
+    async fn test_record_from_start() {
+        let genesis = Genesis::default();
+        let (blocks, state_updates) = mock_blocks(&genesis, 5).await;
+        let hotshot = MockHotShot::default();
+        for block in &blocks {
+            hotshot.push_block(block.header.clone(), block.proof.clone());
+        }
+
+        // Record blocks 2 and 3
+        let fixtures =
+            Fixtures::record_blocks(&hotshot, genesis.clone(), state_updates.clone(), 2, Some(2))
+                .await
+                .unwrap();
+        assert_eq!(fixtures.start, 2);
+        assert_eq!(
+            fixtures
+                .blocks
+                .iter()
+                .map(|block| block.height)
+                .collect::<Vec<_>>(),
+            [2, 3]
+        );
+        assert_eq!(fixtures.state_updates, state_updates[1..4]);
+
+        // Replay starts from the snapshot, which matches the commitment after block 1, and only
+        // executes the recorded blocks
+        let snapshot = genesis.restore(fixtures.snapshot.clone().unwrap());
+        assert_eq!(
+            commitment_to_u256(snapshot.commit()),
+            state_updates[1].state_commitment
+        );
+        assert_eq!(snapshot.block_height(), 1);
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("fixtures.json");
+        fixtures.save(&path).unwrap();
+        let report = Fixtures::load(&path).unwrap().replay().await.unwrap();
+        assert_eq!(
+            report,
+            ReplayReport {
+                blocks: 2,
+                state_updates: 3,
+                state_commitment: state_updates[3].state_commitment,
+            }
+        );
+
+        // Without a snapshot, the fixtures cannot be replayed
+        let mut missing = fixtures.clone();
+        missing.snapshot = None;
+        let err = missing.replay().await.unwrap_err();
+        assert!(matches!(err, FixtureError::MissingSnapshot { start: 2 }));
+
+        // A diverging snapshot is caught by the state update for the start height
+        let mut diverging = fixtures.clone();
+        diverging.snapshot = Some(genesis.state().snapshot());
+        let err = diverging.replay().await.unwrap_err();
+        assert!(matches!(
+            err,
+            FixtureError::CommitmentMismatch {
+                block_height: 2,
+                ..
+            }
+        ));
+
+        // By default, blocks are recorded up to the last state update
+        let fixtures =
+            Fixtures::record_blocks(&hotshot, genesis.clone(), state_updates.clone(), 3, None)
+                .await
+                .unwrap();
+        assert_eq!(fixtures.blocks.len(), 2);
+        let report = fixtures.replay().await.unwrap();
+        assert_eq!(report.blocks, 2);
+        assert_eq!(report.state_commitment, state_updates[4].state_commitment);
+
+        // Recording from the first block needs no snapshot
+        let fixtures = Fixtures::record_blocks(&hotshot, genesis, state_updates, 0, None)
+            .await
+            .unwrap();
+        assert!(fixtures.snapshot.is_none());
+        assert_eq!(fixtures.replay().await.unwrap().blocks, 5);
+    }
+
+    /// Replay every fixture recorded under `fixtures/`, as regression tests.
+    #[async_std::test]
+    async fn test_replay_recorded_fixtures() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let fixtures = Fixtures::load(&path).unwrap();
+            if let Err(err) = fixtures.replay().await {
+                panic!("Replaying {} failed: {err}", path.display());
+            }
+        }
+    }
+}
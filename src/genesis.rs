@@ -0,0 +1,132 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! The initial configuration of a rollup.
+
+use ethers::{
+    signers::{LocalWallet, Signer},
+    types::Address,
+};
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, io, path::Path};
+use strum::IntoEnumIterator;
+
+use crate::seed::{SeedIdentity, INITIAL_BALANCE};
+use crate::state::{Amount, State, StateSnapshot};
+use crate::RollupVM;
+
+/// The EIP-155 chain ID of the demo rollup, `ESPR` in ASCII.
+///
+/// Ethereum wallets tell networks apart by their chain ID, and only accept a signature for the
+/// chain it was made for, so the rollup must not share the chain ID of a public network.
+pub const DEFAULT_CHAIN_ID: u64 = 0x4553_5052;
+
+/// The VM ID, Ethereum chain ID and initial balances of a rollup, as read from a JSON genesis file:
+///
+/// ```json
+/// {
+///     "vm_id": 1,
+///     "chain_id": 1163087954,
+///     "balances": {
+///         "0x885ee92eebda03540066a25a57cc625bbee15d5a": 9999
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Genesis {
+    pub vm_id: u64,
+    /// The chain ID for which Ethereum transactions sent to the rollup must be signed. Defaults to
+    /// `DEFAULT_CHAIN_ID`.
+    #[serde(default = "default_chain_id")]
+    pub chain_id: u64,
+    pub balances: BTreeMap<Address, Amount>,
+}
+
+impl Default for Genesis {
+    /// The genesis of the demo: VM ID 1 and chain ID `DEFAULT_CHAIN_ID`, with each preseeded
+    /// identity holding `INITIAL_BALANCE`.
+    fn default() -> Self {
+        let balances = SeedIdentity::iter()
+            .map(|identity| {
+                let wallet = LocalWallet::new(&mut ChaChaRng::seed_from_u64(identity as u64));
+                (wallet.address(), INITIAL_BALANCE)
+            })
+            .collect();
+        Self {
+            vm_id: 1,
+            chain_id: DEFAULT_CHAIN_ID,
+            balances,
+        }
+    }
+}
+
+fn default_chain_id() -> u64 {
+    DEFAULT_CHAIN_ID
+}
+
+impl Genesis {
+    /// Read a genesis from a JSON file.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let genesis: Self = serde_json::from_slice(&bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if genesis.total_supply().is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "initial balances overflow the total supply",
+            ));
+        }
+        Ok(genesis)
+    }
+
+    /// The sum of the initial balances, or `None` if it overflows.
+    pub fn total_supply(&self) -> Option<Amount> {
+        self.balances
+            .values()
+            .try_fold(0, |supply: Amount, balance| supply.checked_add(*balance))
+    }
+
+    pub fn vm(&self) -> RollupVM {
+        RollupVM::new(self.vm_id.into())
+    }
+
+    /// The state of the rollup before any block is executed.
+    pub fn state(&self) -> State {
+        State::from_initial_balances(self.balances.clone(), self.vm()).with_chain_id(self.chain_id)
+    }
+
+    /// The state of the rollup restored from a snapshot of its state after some blocks.
+    pub fn restore(&self, snapshot: StateSnapshot) -> State {
+        State::from_snapshot(snapshot, self.vm()).with_chain_id(self.chain_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use commit::Committable;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_genesis_file() {
+        let genesis = Genesis::default();
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("genesis.json");
+        fs::write(&path, serde_json::to_vec_pretty(&genesis).unwrap()).unwrap();
+        let read = Genesis::from_file(&path).unwrap();
+
+        assert_eq!(read, genesis);
+        assert_eq!(read.state().commit(), genesis.state().commit());
+        assert_eq!(read.balances.len(), SeedIdentity::iter().count());
+
+        // Genesis files written before the chain ID was configurable use the default
+        let genesis: Genesis = serde_json::from_str(r#"{"vm_id": 2, "balances": {}}"#).unwrap();
+        assert_eq!(genesis.chain_id, DEFAULT_CHAIN_ID);
+        assert_eq!(genesis.state().chain_id(), DEFAULT_CHAIN_ID);
+    }
+}
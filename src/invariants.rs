@@ -0,0 +1,253 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Runtime checks of the invariants of the rollup state machine, which the executor can run after
+//! each block.
+
+use commit::{Commitment, Committable};
+use ethers::abi::Address;
+use snafu::Snafu;
+use std::collections::BTreeMap;
+
+use crate::blocks::ExecutedBlock;
+use crate::state::{Amount, Nonce, State};
+
+/// An invariant of the state machine that does not hold after executing a block.
+#[derive(Clone, Debug, Snafu, PartialEq, Eq)]
+pub enum InvariantViolation {
+    #[snafu(display(
+        "Block {height} does not extend the checked state. Expected {expected}, found {actual}"
+    ))]
+    BrokenChain {
+        height: u64,
+        expected: Commitment<State>,
+        actual: Commitment<State>,
+    },
+    #[snafu(display("Block {height} records state {recorded}, but the state is {actual}"))]
+    WrongNewState {
+        height: u64,
+        recorded: Commitment<State>,
+        actual: Commitment<State>,
+    },
+    #[snafu(display("Total supply changed from {expected} to {actual} in block {height}"))]
+    SupplyChanged {
+        height: u64,
+        expected: u128,
+        actual: u128,
+    },
+    #[snafu(display("Balance {balance} of {address} exceeds the total supply in block {height}"))]
+    BalanceExceedsSupply {
+        height: u64,
+        address: Address,
+        balance: Amount,
+    },
+    #[snafu(display("Nonce of {address} is {actual} after block {height}, expected {expected}"))]
+    WrongNonce {
+        height: u64,
+        address: Address,
+        expected: Nonce,
+        actual: Nonce,
+    },
+}
+
+/// Checks that executing a block:
+/// - extends the previously checked state, and results in the state recorded in the block,
+/// - conserves the total supply,
+/// - does not underflow any balance, which would make it exceed the total supply,
+/// - increments the nonce of each sender by one for each of its applied transactions, and leaves
+///   all other nonces unchanged.
+#[derive(Clone, Debug)]
+pub struct InvariantChecker {
+    commitment: Commitment<State>,
+    total_supply: u128,
+    nonces: BTreeMap<Address, Nonce>,
+}
+
+/// The sum of all balances, which cannot overflow.
+fn total_supply(state: &State) -> u128 {
+    state
+        .accounts()
+        .map(|(_, balance, _)| balance as u128)
+        .sum()
+}
+
+impl InvariantChecker {
+    /// Start checking blocks executed from `state`.
+    pub fn new(state: &State) -> Self {
+        Self {
+            commitment: state.commit(),
+            total_supply: state.total_supply() as u128,
+            nonces: state
+                .accounts()
+                .map(|(address, _, nonce)| (address, nonce))
+                .collect(),
+        }
+    }
+
+    /// Check the invariants after executing `block`, resulting in `state`.
+    pub fn check_block(
+        &mut self,
+        block: &ExecutedBlock,
+        state: &State,
+    ) -> Result<(), InvariantViolation> {
+        let height = block.height;
+        if block.old_state != self.commitment {
+            return Err(InvariantViolation::BrokenChain {
+                height,
+                expected: self.commitment,
+                actual: block.old_state,
+            });
+        }
+        let commitment = state.commit();
+        if block.new_state != commitment {
+            return Err(InvariantViolation::WrongNewState {
+                height,
+                recorded: block.new_state,
+                actual: commitment,
+            });
+        }
+
+        // Both the tracked total supply and the sum of all balances must be conserved
+        for supply in [state.total_supply() as u128, total_supply(state)] {
+            if supply != self.total_supply {
+                return Err(InvariantViolation::SupplyChanged {
+                    height,
+                    expected: self.total_supply,
+                    actual: supply,
+                });
+            }
+        }
+        let supply = self.total_supply;
+
+        let mut applied = BTreeMap::<Address, Nonce>::new();
+        for executed in &block.transactions {
+            if let (Some(sender), None) = (executed.sender, &executed.error) {
+                *applied.entry(sender).or_default() += 1;
+            }
+        }
+        let mut nonces = BTreeMap::new();
+        for (address, balance, nonce) in state.accounts() {
+            if balance as u128 > supply {
+                return Err(InvariantViolation::BalanceExceedsSupply {
+                    height,
+                    address,
+                    balance,
+                });
+            }
+            let expected = self.nonces.get(&address).copied().unwrap_or(0)
+                + applied.get(&address).copied().unwrap_or(0);
+            if nonce != expected {
+                return Err(InvariantViolation::WrongNonce {
+                    height,
+                    address,
+                    expected,
+                    actual: nonce,
+                });
+            }
+            nonces.insert(address, nonce);
+        }
+        // Accounts are never removed
+        if let Some((&address, &expected)) = self
+            .nonces
+            .iter()
+            .find(|(address, _)| !nonces.contains_key(address))
+        {
+            return Err(InvariantViolation::WrongNonce {
+                height,
+                address,
+                expected,
+                actual: 0,
+            });
+        }
+
+        self.commitment = commitment;
+        self.nonces = nonces;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::ExecutedTransaction;
+    use crate::transaction::{SignedTransaction, Transaction};
+    use crate::RollupVM;
+    use ethers::signers::{LocalWallet, Signer};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    #[async_std::test]
+    async fn test_invariant_checker() {
+        let alice = LocalWallet::new(&mut ChaChaRng::seed_from_u64(0));
+        let bob = LocalWallet::new(&mut ChaChaRng::seed_from_u64(1));
+        let mut state =
+            State::from_initial_balances([(alice.address(), 100)], RollupVM::new(1.into()));
+        let mut checker = InvariantChecker::new(&state);
+
+        // Apply a transfer, and record it like `execute_block` does
+        let old_state = state.commit();
+        let transaction = Transaction {
+            amount: 10,
+            destination: bob.address(),
+            nonce: 1,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
+        };
+        let transaction = SignedTransaction::new(transaction, &alice).await;
+        state.apply_transaction(&transaction).unwrap();
+        let mut block = ExecutedBlock {
+            height: 0,
+            block: commit::RawCommitmentBuilder::new("Test Block").finalize(),
+            old_state,
+            new_state: state.commit(),
+            transactions: vec![ExecutedTransaction {
+                transaction: Some(transaction.clone()),
+                sender: Some(alice.address()),
+                error: None,
+            }],
+            verified: false,
+        };
+        checker.clone().check_block(&block, &state).unwrap();
+
+        // A transaction recorded as applied must increment the sender's nonce
+        block.transactions.push(ExecutedTransaction {
+            transaction: Some(transaction),
+            sender: Some(alice.address()),
+            error: None,
+        });
+        assert_eq!(
+            checker.clone().check_block(&block, &state),
+            Err(InvariantViolation::WrongNonce {
+                height: 0,
+                address: alice.address(),
+                expected: 2,
+                actual: 1,
+            })
+        );
+        block.transactions.pop();
+
+        // Minting tokens breaks supply conservation
+        let mut minted = State::from_initial_balances(
+            [(alice.address(), 100), (bob.address(), 10)],
+            RollupVM::new(1.into()),
+        );
+        minted
+            .apply_transaction(&block.transactions[0].transaction.clone().unwrap())
+            .unwrap();
+        block.new_state = minted.commit();
+        assert!(matches!(
+            checker.check_block(&block, &minted),
+            Err(InvariantViolation::SupplyChanged {
+                expected: 100,
+                actual: 110,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! The L1 contract reads and writes the executor depends on.
+
+use async_trait::async_trait;
+use contract_bindings::example_rollup::{self, ExampleRollup};
+use ethers::prelude::*;
+use futures::{stream::BoxStream, StreamExt};
+use hotshot_contract_bindings::hot_shot::{HotShot, NewBlocksFilter};
+use sequencer_utils::contract_send;
+use snafu::Snafu;
+use std::sync::Arc;
+
+/// An error reading from or writing to the L1 contracts.
+#[derive(Clone, Debug, Snafu)]
+#[snafu(display("L1 error: {message}"))]
+pub struct L1Error {
+    pub message: String,
+}
+
+impl L1Error {
+    pub fn new(message: impl ToString) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+/// A range of blocks committed to the HotShot contract.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NewBlocks {
+    pub first_block: u64,
+    pub num_blocks: u64,
+}
+
+/// The interface of the executor to L1: the HotShot contract, from which it learns which blocks to
+/// execute, and the rollup contract, to which it sends proofs.
+#[async_trait]
+pub trait RollupL1: Send + Sync {
+    /// Read the commitment stored by the HotShot contract for the block at `height`.
+    async fn commitment(&self, height: u64) -> Result<U256, L1Error>;
+
+    /// Read the number of blocks committed to the HotShot contract.
+    async fn block_height(&self) -> Result<u64, L1Error>;
+
+    /// Subscribe to the blocks committed to the HotShot contract, starting from the first block.
+    async fn new_blocks(&self) -> Result<BoxStream<'_, Result<NewBlocks, L1Error>>, L1Error>;
+
+    /// Send a proof to the rollup contract that the next `num_blocks` committed blocks transition
+    /// the rollup to `state_comm`. Returns the gas used by the transaction, if known.
+    async fn verify_blocks(
+        &self,
+        num_blocks: u64,
+        state_comm: U256,
+        proof: example_rollup::BatchProof,
+    ) -> Result<Option<U256>, L1Error>;
+}
+
+/// The deployed HotShot and rollup contracts.
+///
+/// HotShot contract events are read from a websocket provider, while proofs are sent with the
+/// middleware `M`, which must sign for the prover account.
+#[derive(Debug)]
+pub struct ContractL1<M> {
+    socket_provider: Arc<Provider<Ws>>,
+    hotshot_contract: HotShot<Provider<Ws>>,
+    rollup_contract: ExampleRollup<M>,
+}
+
+impl<M: Middleware> ContractL1<M> {
+    pub fn new(
+        socket_provider: Provider<Ws>,
+        hotshot_address: Address,
+        rollup_address: Address,
+        l1: M,
+    ) -> Self {
+        let socket_provider = Arc::new(socket_provider);
+        Self {
+            hotshot_contract: HotShot::new(hotshot_address, socket_provider.clone()),
+            rollup_contract: ExampleRollup::new(rollup_address, Arc::new(l1)),
+            socket_provider,
+        }
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> RollupL1 for ContractL1<M> {
+    async fn commitment(&self, height: u64) -> Result<U256, L1Error> {
+        self.hotshot_contract
+            .commitments(height.into())
+            .call()
+            .await
+            .map_err(L1Error::new)
+    }
+
+    async fn block_height(&self) -> Result<u64, L1Error> {
+        let height = self
+            .hotshot_contract
+            .block_height()
+            .call()
+            .await
+            .map_err(L1Error::new)?;
+        Ok(height.as_u64())
+    }
+
+    async fn new_blocks(&self) -> Result<BoxStream<'_, Result<NewBlocks, L1Error>>, L1Error> {
+        let filter = self
+            .hotshot_contract
+            .new_blocks_filter()
+            .from_block(0)
+            // Ethers does not set the contract address on filters created via contract bindings. This
+            // seems like a bug and I have reported it: https://github.com/gakonst/ethers-rs/issues/2528.
+            // In the mean time we can work around by setting the address manually.
+            .address(self.hotshot_contract.address().into())
+            .filter;
+        let logs = self
+            .socket_provider
+            .subscribe_logs(&filter)
+            .await
+            .map_err(L1Error::new)?;
+        Ok(logs
+            .map(|log| {
+                let NewBlocksFilter {
+                    first_block_number,
+                    num_blocks,
+                } = parse_log(log).map_err(L1Error::new)?;
+                Ok(NewBlocks {
+                    first_block: first_block_number.as_u64(),
+                    num_blocks: num_blocks.as_u64(),
+                })
+            })
+            .boxed())
+    }
+
+    async fn verify_blocks(
+        &self,
+        num_blocks: u64,
+        state_comm: U256,
+        proof: example_rollup::BatchProof,
+    ) -> Result<Option<U256>, L1Error> {
+        let call = self
+            .rollup_contract
+            .verify_blocks(num_blocks, state_comm, proof);
+        let (receipt, _) = contract_send(&call).await.map_err(L1Error::new)?;
+        Ok(receipt.gas_used)
+    }
+}
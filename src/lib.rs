@@ -9,16 +9,31 @@ use clap::Parser;
 use derive_more::{From, Into};
 use ethers::types::Address;
 use sequencer::{Vm, VmId};
+use std::path::PathBuf;
 use surf_disco::Url;
 use transaction::SignedTransaction;
 
 // Internal modules for various functionality in the system.
 pub mod api;
+pub mod blocks;
+pub mod client;
 pub mod error;
 pub mod executor;
+pub mod fixtures;
+pub mod genesis;
+pub mod invariants;
+pub mod l1;
+pub mod mempool;
+pub mod metrics;
+pub mod multisig;
 mod prover;
+pub mod query;
+pub mod rpc;
 pub mod seed;
 pub mod state;
+pub mod status;
+#[cfg(test)]
+mod testing;
 pub mod transaction;
 pub mod utils;
 
@@ -74,6 +89,21 @@ pub struct Options {
     /// Index of the account derived from the mnemonic that will send proofs to the rollup contract.
     #[clap(long, env = "ESPRESSO_DEMO_ROLLUP_ACCOUNT_INDEX", default_value = "1")]
     pub rollup_account_index: u32,
+
+    /// JSON file with the VM ID, Ethereum chain ID and initial balances of the rollup.
+    /// If not provided, the demo genesis is used: VM ID 1, with each preseeded identity funded.
+    #[clap(long, env = "ESPRESSO_DEMO_GENESIS_FILE")]
+    pub genesis_file: Option<PathBuf>,
+
+    /// Check the invariants of the state machine after executing each block, stopping the executor
+    /// if one is violated.
+    #[clap(long, env = "ESPRESSO_DEMO_CHECK_INVARIANTS")]
+    pub check_invariants: bool,
+
+    /// Number of most recent executed blocks kept in memory and served by the block explorer and
+    /// history endpoints.
+    #[clap(long, env = "ESPRESSO_DEMO_RETAINED_BLOCKS", default_value = "10000")]
+    pub retained_blocks: usize,
 }
 
 /// `RollupVM` struct represents a virtual machine (VM) in the rollup system.
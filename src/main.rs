@@ -4,25 +4,26 @@
 // You should have received a copy of the MIT License
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
-use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+use async_compatibility_layer::{
+    async_primitives::broadcast,
+    logging::{setup_backtrace, setup_logging},
+};
 use async_std::sync::RwLock;
 use clap::Parser;
 use commit::Committable;
-use ethers::signers::{LocalWallet, Signer};
+use ethers::signers::{coins_bip39::English, MnemonicBuilder, Signer};
 use example_l2::{
     api::{serve, APIOptions},
+    blocks::BlockStore,
     executor::{run_executor, ExecutorOptions},
-    seed::{SeedIdentity, INITIAL_BALANCE},
-    state::State,
+    genesis::Genesis,
+    status::StatusOptions,
     utils::{create_provider, deploy_example_contract},
-    Options, RollupVM,
+    Options,
 };
 use futures::join;
-use rand::SeedableRng;
-use rand_chacha::ChaChaRng;
 use sequencer_utils::test_utils::TestL1System;
 use std::sync::Arc;
-use strum::IntoEnumIterator;
 
 #[async_std::main]
 async fn main() {
@@ -33,31 +34,22 @@ async fn main() {
     // Parse command-line arguments using the Options struct
     let opt = Options::parse();
 
-    // Initialize the Rollup Virtual Machine (VM) with a unique ID (1)
-    let vm = RollupVM::new(1.into());
-
-    // Initialize a vector to store initial account balances
-    let mut initial_balances = vec![];
-
     /*
-    Generate initial account balances for predefined identities.
-
-    This loop iterates over a list of identities (SeedIdentity),
-    generating a unique wallet address for each identity using a
-    cryptographically secure random number generator (ChaChaRng).
-    The generated address and an associated initial balance are
-    stored in the `initial_balances` vector.
+    Load the genesis of the rollup: its VM ID, Ethereum chain ID and initial account balances.
 
-    Each identity (e.g., Alice, Bob, Charlie) is deterministically
-    linked to a wallet using a seed derived from its enum value.
+    Unless a genesis file is provided, the demo genesis is used. It
+    runs the Rollup Virtual Machine (VM) with ID 1, and funds a list
+    of predefined identities (SeedIdentity). Each identity (e.g.,
+    Alice, Bob, Charlie) is deterministically linked to a wallet
+    using a seed derived from its enum value.
     */
-    for identity in SeedIdentity::iter() {
-        let address = LocalWallet::new(&mut ChaChaRng::seed_from_u64(identity as u64)).address();
-        initial_balances.push((address, INITIAL_BALANCE));
-    }
+    let genesis = match &opt.genesis_file {
+        Some(path) => Genesis::from_file(path).expect("Error reading the genesis file"),
+        None => Genesis::default(),
+    };
 
     /*
-    Initialize the rollup's state with the generated account balances.
+    Initialize the rollup's state with the genesis account balances.
 
     The `State` structure encapsulates the rollup's account state,
     which includes:
@@ -74,35 +66,13 @@ async fn main() {
     The state is protected by an `RwLock` to ensure thread-safe
     asynchronous access and shared using an `Arc`.
     */
-    let state = Arc::new(RwLock::new(State::from_initial_balances(
-        initial_balances,
-        vm,
-    )));
-
-    /*
-    Set up the API options for the rollup.
-
-    `APIOptions` configures how the user interacts with the rollup,
-    allowing them to submit transactions either via the rollup API
-    or directly to the HotShot sequencer node API. These options
-    include the API port and the URL of the sequencer node.
-    */
-    let api_options = APIOptions {
-        api_port: opt.api_port,
-        sequencer_url: opt.sequencer_url.clone(),
-    };
+    let state = Arc::new(RwLock::new(genesis.state()));
 
-    /*
-    Start the API server to handle user transactions and queries.
+    // Channel over which the executor publishes the rollup state after each block
+    let (executor_send, _) = broadcast::channel();
 
-    The `serve_api` async block starts a server that listens for
-    transaction submissions and state queries. The server interacts
-    with the shared rollup state to read or modify the state based
-    on user input.
-    */
-    let serve_api = async {
-        serve(&api_options, state.clone()).await.unwrap();
-    };
+    // Blocks recorded by the executor, served by the API's block explorer endpoints
+    let block_store = Arc::new(RwLock::new(BlockStore::with_retention(opt.retained_blocks)));
 
     // Generate an initial state commitment, which is used for verifiable rollup state transitions.
     let initial_state = { state.read().await.commit() };
@@ -128,7 +98,56 @@ async fn main() {
         rollup_account_index: opt.rollup_account_index,
         rollup_mnemonic: opt.rollup_mnemonic.clone(),
         sequencer_url: opt.sequencer_url.clone(),
-        output_stream: None,
+        output_stream: Some(executor_send.clone()),
+        block_store: block_store.clone(),
+        check_invariants: opt.check_invariants,
+    };
+
+    // Derive the address of the account that sends proofs to the rollup contract
+    let prover_address = MnemonicBuilder::<English>::default()
+        .phrase(opt.rollup_mnemonic.as_str())
+        .index(opt.rollup_account_index)
+        .unwrap()
+        .build()
+        .unwrap()
+        .address();
+
+    /*
+    Set up the API options for the rollup.
+
+    `APIOptions` configures how the user interacts with the rollup,
+    allowing them to submit transactions either via the rollup API
+    or directly to the HotShot sequencer node API. These options
+    include the API port and the URL of the sequencer node, as well
+    as the executor's state stream, which the API uses to release
+    transactions held in its mempool in nonce order, and the L1
+    configuration of the executor, which the API reports on in its
+    status endpoints.
+    */
+    let api_options = APIOptions {
+        api_port: opt.api_port,
+        sequencer_url: opt.sequencer_url.clone(),
+        executor_stream: Some(executor_send.clone()),
+        block_store: block_store.clone(),
+        status_options: Some(StatusOptions {
+            l1_http_provider: opt.l1_http_provider.clone(),
+            l1_ws_provider: opt.l1_ws_provider.clone(),
+            hotshot_address: opt.hotshot_address,
+            rollup_address: rollup_contract.address(),
+            prover_address,
+        }),
+    };
+
+    /*
+    Start the API server to handle user transactions and queries.
+
+    The `serve_api` async block starts a server that listens for
+    transaction submissions and state queries. The server interacts
+    with the shared rollup state to read or modify the state based
+    on user input.
+    */
+    let serve_api = async {
+        serve(&api_options, state.clone()).await.unwrap();
     };
 
     tracing::info!("Launching Example Rollup API and Executor");
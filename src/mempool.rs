@@ -0,0 +1,368 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+use ethers::abi::Address;
+use snafu::Snafu;
+use std::collections::BTreeMap;
+
+use crate::state::{Nonce, State};
+use crate::transaction::SignedTransaction;
+
+/// How far past the sender's next expected nonce a transaction may be and still be held.
+pub const MAX_NONCE_GAP: Nonce = 64;
+
+/// The maximum number of transactions held in the pool, across all senders.
+pub const MAX_PENDING: usize = 4096;
+
+/// The number of blocks a transaction is held for before it is dropped, if its predecessors have
+/// not been applied by then.
+pub const HOLD_BLOCKS: u64 = 100;
+
+/// An error adding a transaction to the mempool.
+#[derive(Snafu, Clone, Debug, PartialEq, Eq)]
+pub enum MempoolError {
+    #[snafu(display(
+        "Nonce {actual} of {address} is too far ahead, at most nonce {max} can be held."
+    ))]
+    NonceTooFarAhead {
+        address: Address,
+        max: Nonce,
+        actual: Nonce,
+    },
+    #[snafu(display("The mempool is full, try again once pending transactions are released."))]
+    Full,
+}
+
+/// A local pool of transactions whose nonces are ahead of the sender's next expected nonce.
+///
+/// HotShot does not guarantee that two transactions submitted in quick succession are sequenced in
+/// submission order. If nonce 3 is sequenced before nonce 2, it is rejected by the executor for good.
+/// The mempool holds such future-nonce transactions back and releases them one at a time, only once
+/// the executor has applied the previous nonce.
+///
+/// The pool is bounded. Each sender may only hold nonces up to `MAX_NONCE_GAP` past its next
+/// expected nonce, and once `capacity` transactions are held, a new transaction evicts the highest
+/// nonce of the sender holding the most transactions, or is refused if that would be the sender
+/// itself. Transactions which are still held `HOLD_BLOCKS` blocks after they were added, or whose
+/// validity window has passed, are dropped.
+#[derive(Debug)]
+pub struct Mempool {
+    pending: BTreeMap<Address, BTreeMap<Nonce, Held>>,
+    len: usize,
+    capacity: usize,
+}
+
+/// A transaction held in the pool, with the block height at which it was added.
+#[derive(Debug)]
+struct Held {
+    transaction: SignedTransaction,
+    since: u64,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self::with_capacity(MAX_PENDING)
+    }
+}
+
+impl Mempool {
+    /// Create a mempool holding at most `capacity` transactions.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            pending: Default::default(),
+            len: 0,
+            capacity,
+        }
+    }
+
+    /// The number of transactions held in the pool.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the pool holds no transactions.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Add a transaction from `sender` to the pool, given the executed `state`.
+    ///
+    /// A pending transaction with the same sender and nonce is replaced, and returned.
+    pub fn insert(
+        &mut self,
+        sender: Address,
+        transaction: SignedTransaction,
+        state: &State,
+    ) -> Result<Option<SignedTransaction>, MempoolError> {
+        let nonce = transaction.transaction.nonce;
+        let max = state.get_nonce(&sender).saturating_add(MAX_NONCE_GAP);
+        if nonce > max {
+            return Err(MempoolError::NonceTooFarAhead {
+                address: sender,
+                max,
+                actual: nonce,
+            });
+        }
+
+        let held = self.pending.get(&sender);
+        let replacing = held.is_some_and(|txns| txns.contains_key(&nonce));
+        if !replacing && self.len >= self.capacity {
+            self.evict(held.map_or(0, BTreeMap::len))?;
+        }
+        let held = Held {
+            transaction,
+            since: state.block_height(),
+        };
+        let replaced = self.pending.entry(sender).or_default().insert(nonce, held);
+        if replaced.is_none() {
+            self.len += 1;
+        }
+        Ok(replaced.map(|held| held.transaction))
+    }
+
+    /// Make room for a transaction from a sender holding `held` transactions, by evicting the
+    /// highest nonce of the sender holding the most transactions, if it holds more than `held`.
+    fn evict(&mut self, held: usize) -> Result<(), MempoolError> {
+        let Some((&victim, txns)) = self.pending.iter_mut().max_by_key(|(_, txns)| txns.len())
+        else {
+            return Err(MempoolError::Full);
+        };
+        if txns.len() <= held {
+            return Err(MempoolError::Full);
+        }
+        txns.pop_last();
+        if txns.is_empty() {
+            self.pending.remove(&victim);
+        }
+        self.len -= 1;
+        Ok(())
+    }
+
+    /// List the pending transactions of an address, in nonce order.
+    pub fn pending(&self, address: &Address) -> Vec<SignedTransaction> {
+        self.pending
+            .get(address)
+            .map(|txns| txns.values().map(|held| held.transaction.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Remove and return every transaction that is next in line given the executed `state`.
+    ///
+    /// Transactions whose nonce has already been used are stale and are dropped from the pool, as
+    /// are transactions held for more than `HOLD_BLOCKS` blocks or past their validity window.
+    pub fn take_ready(&mut self, state: &State) -> Vec<SignedTransaction> {
+        let height = state.block_height();
+        let mut ready = vec![];
+        self.pending.retain(|address, txns| {
+            let next_nonce = state.get_nonce(address) + 1;
+            *txns = txns.split_off(&next_nonce);
+            txns.retain(|_, held| {
+                height <= held.since.saturating_add(HOLD_BLOCKS)
+                    && !held.transaction.transaction.is_expired_after(height)
+            });
+            if let Some(held) = txns.remove(&next_nonce) {
+                ready.push(held.transaction);
+            }
+            !txns.is_empty()
+        });
+        self.len = self.pending.values().map(BTreeMap::len).sum();
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::mock_block;
+    use crate::transaction::Transaction;
+    use crate::RollupVM;
+    use ethers::signers::{LocalWallet, Signer};
+    use sequencer::Vm;
+
+    #[async_std::test]
+    async fn test_nonce_ordering() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(1.into());
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+        let mut state = State::from_initial_balances([(alice.address(), 100)], vm);
+        let mut mempool = Mempool::default();
+
+        let mut txns = vec![];
+        for nonce in 1..=3 {
+            let transaction = Transaction {
+                amount: nonce,
+                destination: bob.address(),
+                nonce,
+                valid_after: None,
+                valid_until: None,
+                recipients: vec![],
+                multisig: None,
+                multisig_op: None,
+            };
+            txns.push(SignedTransaction::new(transaction, &alice).await);
+        }
+
+        // Nonces 2 and 3 arrive before nonce 1 has been applied.
+        assert!(mempool
+            .insert(alice.address(), txns[2].clone(), &state)
+            .unwrap()
+            .is_none());
+        assert!(mempool
+            .insert(alice.address(), txns[1].clone(), &state)
+            .unwrap()
+            .is_none());
+        assert_eq!(mempool.pending(&alice.address()).len(), 2);
+        assert!(mempool.take_ready(&state).is_empty());
+
+        // Replacing a pending nonce returns the old transaction.
+        let replaced = mempool
+            .insert(alice.address(), txns[1].clone(), &state)
+            .unwrap()
+            .expect("Pending transaction should be replaced");
+        assert_eq!(replaced.transaction.nonce, 2);
+
+        // Once nonce 1 is applied, only nonce 2 is released.
+        state.apply_transaction(&txns[0]).unwrap();
+        let ready = mempool.take_ready(&state);
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].transaction.nonce, 2);
+        assert!(mempool.take_ready(&state).is_empty());
+
+        // Once nonce 2 is applied, nonce 3 is released and the pool is empty.
+        state.apply_transaction(&ready[0]).unwrap();
+        let ready = mempool.take_ready(&state);
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].transaction.nonce, 3);
+        assert!(mempool.pending(&alice.address()).is_empty());
+        assert!(mempool.is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_limits() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(1.into());
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+        let state = State::from_initial_balances([(alice.address(), 100), (bob.address(), 0)], vm);
+        let mut mempool = Mempool::with_capacity(3);
+        let transfer = |nonce| Transaction {
+            amount: 0,
+            destination: Address::zero(),
+            nonce,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
+        };
+
+        // Nonces past the window are refused
+        let txn = SignedTransaction::new(transfer(MAX_NONCE_GAP + 1), &alice).await;
+        assert_eq!(
+            mempool.insert(alice.address(), txn, &state).unwrap_err(),
+            MempoolError::NonceTooFarAhead {
+                address: alice.address(),
+                max: MAX_NONCE_GAP,
+                actual: MAX_NONCE_GAP + 1,
+            }
+        );
+
+        // Fill the pool with Alice's transactions
+        for nonce in 2..=4 {
+            let txn = SignedTransaction::new(transfer(nonce), &alice).await;
+            mempool.insert(alice.address(), txn, &state).unwrap();
+        }
+        assert_eq!(mempool.len(), 3);
+
+        // Replacing a pending nonce does not need room
+        let txn = SignedTransaction::new(transfer(4), &alice).await;
+        assert!(mempool
+            .insert(alice.address(), txn, &state)
+            .unwrap()
+            .is_some());
+
+        // Alice can't evict her own transactions
+        let txn = SignedTransaction::new(transfer(5), &alice).await;
+        assert_eq!(
+            mempool.insert(alice.address(), txn, &state).unwrap_err(),
+            MempoolError::Full
+        );
+
+        // Bob's transaction evicts Alice's highest nonce
+        let txn = SignedTransaction::new(transfer(2), &bob).await;
+        mempool.insert(bob.address(), txn, &state).unwrap();
+        assert_eq!(mempool.len(), 3);
+        let nonces = mempool
+            .pending(&alice.address())
+            .iter()
+            .map(|txn| txn.transaction.nonce)
+            .collect::<Vec<_>>();
+        assert_eq!(nonces, [2, 3]);
+        assert_eq!(mempool.pending(&bob.address()).len(), 1);
+    }
+
+    /// Execute an empty block at `height`.
+    async fn advance(state: &mut State, height: u64) {
+        let (header, proof) = mock_block(&[], state.vm.id());
+        state
+            .execute_block(height, header.transactions_root, proof)
+            .await;
+    }
+
+    #[async_std::test]
+    async fn test_expiry() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(1.into());
+        let alice = LocalWallet::new(&mut rng);
+        let mut state = State::from_initial_balances([(alice.address(), 100)], vm);
+        let mut mempool = Mempool::default();
+        let transfer = |nonce, valid_until| Transaction {
+            amount: 0,
+            destination: Address::zero(),
+            nonce,
+            valid_after: None,
+            valid_until,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
+        };
+        // Nonce 1 is never sent, so nonces 2 and 3 stay held until they time out
+        for (nonce, valid_until) in [(2, None), (3, Some(10))] {
+            let txn = SignedTransaction::new(transfer(nonce, valid_until), &alice).await;
+            mempool.insert(alice.address(), txn, &state).unwrap();
+        }
+        assert!(mempool.take_ready(&state).is_empty());
+        assert_eq!(mempool.len(), 2);
+
+        // Nonce 3 is dropped once its validity window has passed
+        advance(&mut state, 10).await;
+        assert!(mempool.take_ready(&state).is_empty());
+        assert_eq!(mempool.len(), 1);
+
+        // Nonce 2 is dropped after `HOLD_BLOCKS` blocks
+        advance(&mut state, HOLD_BLOCKS).await;
+        assert!(mempool.take_ready(&state).is_empty());
+        assert_eq!(mempool.len(), 1);
+        advance(&mut state, HOLD_BLOCKS + 1).await;
+        assert!(mempool.take_ready(&state).is_empty());
+        assert!(mempool.is_empty());
+
+        // A held nonce is dropped once the sender's nonce has passed it
+        let txns = [1, 2].map(|nonce| transfer(nonce, None));
+        let mut signed = vec![];
+        for txn in txns {
+            signed.push(SignedTransaction::new(txn, &alice).await);
+        }
+        mempool
+            .insert(alice.address(), signed[1].clone(), &state)
+            .unwrap();
+        state.apply_transaction(&signed[0]).unwrap();
+        state.apply_transaction(&signed[1]).unwrap();
+        assert!(mempool.take_ready(&state).is_empty());
+        assert!(mempool.is_empty());
+    }
+}
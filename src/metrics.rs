@@ -0,0 +1,147 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+use prometheus::{
+    Histogram, HistogramOpts, HistogramTimer, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    Opts, Registry,
+};
+use std::sync::OnceLock;
+
+use crate::error::RollupError;
+
+/// Prometheus metrics for the executor, prover and API, served at `rollup/metrics`.
+///
+/// The metrics are process wide, so that they can be recorded wherever the corresponding event
+/// happens without threading a handle through the state.
+#[derive(Debug)]
+pub struct Metrics {
+    pub registry: Registry,
+    /// Blocks executed by the executor.
+    pub blocks_executed: IntCounter,
+    /// Transactions applied to the state.
+    pub transactions_applied: IntCounter,
+    /// Transactions rejected by the state, labeled by `RollupError` variant.
+    pub transactions_rejected: IntCounterVec,
+    /// Namespace leaves that could not be decoded as rollup transactions.
+    pub malformed_leaves: IntCounter,
+    /// Time taken to execute a block and generate its proof.
+    pub block_execution_seconds: Histogram,
+    /// Time from receiving a batch of blocks from L1 until its proof is verified by the rollup contract.
+    pub proof_seconds: Histogram,
+    /// Failed attempts to send a proof to the rollup contract.
+    pub verify_retries: IntCounter,
+    /// Gas used by successful `verifyBlocks` calls.
+    pub verify_gas_used: IntCounter,
+    /// Blocks committed to the HotShot contract but not yet executed.
+    pub hotshot_lag: IntGauge,
+    /// Blocks executed but not yet verified by the rollup contract.
+    pub l1_lag: IntGauge,
+    /// API requests, labeled by route.
+    pub api_requests: IntCounterVec,
+    /// API request latency, labeled by route.
+    pub api_request_seconds: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("example_rollup".into()), None)
+            .expect("Metrics registry should be valid");
+        let metrics = Self {
+            blocks_executed: IntCounter::new("blocks_executed", "Blocks executed").unwrap(),
+            transactions_applied: IntCounter::new("transactions_applied", "Transactions applied")
+                .unwrap(),
+            transactions_rejected: IntCounterVec::new(
+                Opts::new("transactions_rejected", "Transactions rejected, by error"),
+                &["error"],
+            )
+            .unwrap(),
+            malformed_leaves: IntCounter::new("malformed_leaves", "Malformed namespace leaves")
+                .unwrap(),
+            block_execution_seconds: Histogram::with_opts(HistogramOpts::new(
+                "block_execution_seconds",
+                "Time taken to execute a block and generate its proof",
+            ))
+            .unwrap(),
+            proof_seconds: Histogram::with_opts(
+                HistogramOpts::new(
+                    "proof_seconds",
+                    "Time from receiving a batch of blocks until its proof is verified on L1",
+                )
+                .buckets(prometheus::exponential_buckets(0.5, 2.0, 10).unwrap()),
+            )
+            .unwrap(),
+            verify_retries: IntCounter::new("verify_retries", "Failed attempts to verify blocks")
+                .unwrap(),
+            verify_gas_used: IntCounter::new("verify_gas_used", "Gas used to verify blocks")
+                .unwrap(),
+            hotshot_lag: IntGauge::new("hotshot_lag", "HotShot blocks not yet executed").unwrap(),
+            l1_lag: IntGauge::new("l1_lag", "Executed blocks not yet verified on L1").unwrap(),
+            api_requests: IntCounterVec::new(
+                Opts::new("api_requests", "API requests, by route"),
+                &["route"],
+            )
+            .unwrap(),
+            api_request_seconds: HistogramVec::new(
+                HistogramOpts::new("api_request_seconds", "API request latency, by route"),
+                &["route"],
+            )
+            .unwrap(),
+            registry,
+        };
+        metrics
+            .register()
+            .expect("Metrics should only be registered once");
+        metrics
+    }
+
+    fn register(&self) -> prometheus::Result<()> {
+        self.registry
+            .register(Box::new(self.blocks_executed.clone()))?;
+        self.registry
+            .register(Box::new(self.transactions_applied.clone()))?;
+        self.registry
+            .register(Box::new(self.transactions_rejected.clone()))?;
+        self.registry
+            .register(Box::new(self.malformed_leaves.clone()))?;
+        self.registry
+            .register(Box::new(self.block_execution_seconds.clone()))?;
+        self.registry
+            .register(Box::new(self.proof_seconds.clone()))?;
+        self.registry
+            .register(Box::new(self.verify_retries.clone()))?;
+        self.registry
+            .register(Box::new(self.verify_gas_used.clone()))?;
+        self.registry.register(Box::new(self.hotshot_lag.clone()))?;
+        self.registry.register(Box::new(self.l1_lag.clone()))?;
+        self.registry
+            .register(Box::new(self.api_requests.clone()))?;
+        self.registry
+            .register(Box::new(self.api_request_seconds.clone()))?;
+        Ok(())
+    }
+
+    /// Record a transaction rejected by the state.
+    pub fn reject_transaction(&self, err: &RollupError) {
+        let variant: &'static str = err.into();
+        self.transactions_rejected
+            .with_label_values(&[variant])
+            .inc();
+    }
+
+    /// Record an API request to `route`. The latency is recorded when the returned timer is dropped.
+    pub fn api_request(&self, route: &str) -> HistogramTimer {
+        self.api_requests.with_label_values(&[route]).inc();
+        self.api_request_seconds
+            .with_label_values(&[route])
+            .start_timer()
+    }
+}
+
+/// The process wide metrics.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
@@ -0,0 +1,139 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! Multisig accounts, which are controlled by a set of owners rather than by a single key.
+//!
+//! A multisig account has no key of its own. Its address is derived from the account which
+//! created it and the nonce of the creating transaction, and transactions sent from it must be
+//! signed by at least `threshold` of its owners.
+
+use ethers::{abi::Address, utils::keccak256};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use crate::error::RollupError;
+use crate::state::Nonce;
+
+/// The maximum number of owners of a multisig account, which bounds the number of signatures
+/// checked for a single transaction.
+pub const MAX_OWNERS: usize = 16;
+
+/// The owners of a multisig account, and the number of them which must sign its transactions.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Multisig {
+    pub owners: Vec<Address>,
+    pub threshold: u32,
+}
+
+/// An operation on the owners of a multisig account.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MultisigOp {
+    /// Create a multisig account at `Multisig::address(sender, nonce)`.
+    Create(Multisig),
+    /// Replace the owners of the multisig account sending the transaction.
+    SetOwners(Multisig),
+}
+
+impl Multisig {
+    /// The address of the multisig account created by `creator` with the transaction `nonce`.
+    ///
+    /// The address is a hash, so no one holds a key for it.
+    pub fn address(creator: Address, nonce: Nonce) -> Address {
+        let mut preimage = b"Multisig Account".to_vec();
+        preimage.extend_from_slice(creator.as_bytes());
+        preimage.extend_from_slice(&nonce.to_be_bytes());
+        Address::from_slice(&keccak256(preimage)[12..])
+    }
+
+    /// Check that there is at least one and at most `MAX_OWNERS` owners, without duplicates, and
+    /// that the threshold can be met.
+    pub fn validate(&self) -> Result<(), RollupError> {
+        let invalid = |reason: String| Err(RollupError::InvalidMultisigOwners { reason });
+        if self.owners.is_empty() || self.owners.len() > MAX_OWNERS {
+            return invalid(format!(
+                "expected between 1 and {MAX_OWNERS} owners, found {}",
+                self.owners.len()
+            ));
+        }
+        if self.owners.iter().collect::<BTreeSet<_>>().len() != self.owners.len() {
+            return invalid("owners must be distinct".into());
+        }
+        if self.threshold == 0 || self.threshold as usize > self.owners.len() {
+            return invalid(format!(
+                "the threshold must be between 1 and the number of owners, found {}",
+                self.threshold
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that `signers` include at least `threshold` distinct owners of the account at
+    /// `address`. Signers which are not owners are ignored.
+    pub fn authorize(&self, address: Address, signers: &[Address]) -> Result<(), RollupError> {
+        let owners = signers
+            .iter()
+            .filter(|signer| self.owners.contains(*signer))
+            .collect::<BTreeSet<_>>()
+            .len() as u32;
+        if owners < self.threshold {
+            return Err(RollupError::InsufficientSignatures {
+                address,
+                threshold: self.threshold,
+                signers: owners,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multisig() {
+        let [alice, bob, carol] = [1, 2, 3].map(Address::repeat_byte);
+        let multisig = Multisig {
+            owners: vec![alice, bob, carol],
+            threshold: 2,
+        };
+        multisig.validate().unwrap();
+
+        // Addresses are unique to the creator and nonce
+        assert_ne!(Multisig::address(alice, 1), Multisig::address(alice, 2));
+        assert_ne!(Multisig::address(alice, 1), Multisig::address(bob, 1));
+
+        // Duplicate signatures and signatures of non-owners do not count towards the threshold
+        let address = Multisig::address(alice, 1);
+        multisig.authorize(address, &[alice, carol]).unwrap();
+        assert_eq!(
+            multisig.authorize(address, &[alice, alice, Address::zero()]),
+            Err(RollupError::InsufficientSignatures {
+                address,
+                threshold: 2,
+                signers: 1,
+            })
+        );
+
+        // Invalid owners
+        for (owners, threshold) in [
+            (vec![], 0),
+            (vec![alice, bob], 0),
+            (vec![alice, bob], 3),
+            (vec![alice, alice], 1),
+            (vec![alice; MAX_OWNERS + 1], 1),
+        ] {
+            let multisig = Multisig { owners, threshold };
+            assert!(
+                matches!(
+                    multisig.validate(),
+                    Err(RollupError::InvalidMultisigOwners { .. })
+                ),
+                "{multisig:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! The HotShot query service endpoints the executor depends on.
+
+use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt};
+use sequencer::{api::endpoints::NamespaceProofQueryData, Header, NamespaceProofType};
+use snafu::Snafu;
+
+pub type HotShotClient = surf_disco::Client<hotshot_query_service::Error>;
+
+/// An error fetching data from the HotShot query service.
+#[derive(Clone, Debug, Snafu)]
+#[snafu(display("HotShot query error: {message}"))]
+pub struct QueryError {
+    pub message: String,
+}
+
+impl QueryError {
+    pub fn new(message: impl ToString) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+/// The availability API of the HotShot query service.
+#[async_trait]
+pub trait HotShotQuery: Send + Sync {
+    /// Subscribe to the headers of sequenced blocks, starting from the block at height `from`.
+    async fn headers(
+        &self,
+        from: u64,
+    ) -> Result<BoxStream<'_, Result<Header, QueryError>>, QueryError>;
+
+    /// Fetch the proof of the transactions in the namespace of `vm_id` in the block at `height`.
+    async fn namespace_proof(
+        &self,
+        height: u64,
+        vm_id: u64,
+    ) -> Result<NamespaceProofType, QueryError>;
+}
+
+#[async_trait]
+impl HotShotQuery for HotShotClient {
+    async fn headers(
+        &self,
+        from: u64,
+    ) -> Result<BoxStream<'_, Result<Header, QueryError>>, QueryError> {
+        let headers = self
+            .socket(&format!("stream/headers/{from}"))
+            .subscribe::<Header>()
+            .await
+            .map_err(QueryError::new)?;
+        Ok(headers
+            .map(|header| header.map_err(QueryError::new))
+            .boxed())
+    }
+
+    async fn namespace_proof(
+        &self,
+        height: u64,
+        vm_id: u64,
+    ) -> Result<NamespaceProofType, QueryError> {
+        let query: NamespaceProofQueryData = self
+            .get(&format!("block/{height}/namespace/{vm_id}"))
+            .send()
+            .await
+            .map_err(QueryError::new)?;
+        Ok(query.proof)
+    }
+}
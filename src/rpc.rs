@@ -0,0 +1,392 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! An Ethereum JSON-RPC facade over the rollup API.
+//!
+//! The facade maps the handful of methods that wallets need to send value transfers onto the rollup
+//! state and the existing submission path, so that the example rollup can be used with ordinary
+//! Ethereum wallets and SDKs. Balances are reported in the rollup's own units, and the chain ID is
+//! the one configured in the rollup's genesis.
+
+use async_std::sync::RwLock;
+use ethers::{
+    abi::Address,
+    types::{Bytes, TransactionReceipt, H256, U256, U64},
+};
+use futures::FutureExt;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{io, sync::Arc};
+use surf_disco::Url;
+use tide_disco::{error::ServerError, Api, StatusCode};
+
+use crate::api::{submit_or_hold_transaction, validate_transaction};
+use crate::blocks::BlockStore;
+use crate::error::RollupError;
+use crate::mempool::Mempool;
+use crate::state::State;
+use crate::transaction::SignedTransaction;
+
+// JSON-RPC error codes, see EIP-1474.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+const TRANSACTION_REJECTED: i64 = -32003;
+const LIMIT_EXCEEDED: i64 = -32005;
+
+/// Gas reported to Ethereum tooling for a transfer. The rollup itself does not meter gas.
+const TRANSFER_GAS: u64 = 21000;
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Vec<Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcResponse {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+impl RpcResponse {
+    fn new(id: Value, result: Result<Value, RpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn rejected(err: RollupError) -> Self {
+        Self::new(TRANSACTION_REJECTED, err.to_string())
+    }
+}
+
+/// Everything the JSON-RPC handlers need besides the rollup state.
+#[derive(Clone)]
+struct RpcContext {
+    sequencer_url: Url,
+    mempool: Arc<RwLock<Mempool>>,
+    block_store: Arc<RwLock<BlockStore>>,
+}
+
+fn to_json(value: impl Serialize) -> Value {
+    serde_json::to_value(value).expect("Serialization should not fail")
+}
+
+fn param<T: DeserializeOwned>(params: &[Value], index: usize) -> Result<T, RpcError> {
+    let value = params
+        .get(index)
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Missing parameter {index}")))?;
+    serde_json::from_value(value.clone())
+        .map_err(|err| RpcError::new(INVALID_PARAMS, format!("Invalid parameter {index}: {err}")))
+}
+
+impl RpcContext {
+    /// Handles a JSON-RPC request body, which is either a single request or a batch.
+    async fn handle(&self, body: Value, state: &State) -> Value {
+        match body {
+            Value::Array(requests) => {
+                let mut responses = vec![];
+                for request in requests {
+                    responses.push(self.handle_request(request, state).await);
+                }
+                to_json(responses)
+            }
+            request => to_json(self.handle_request(request, state).await),
+        }
+    }
+
+    async fn handle_request(&self, request: Value, state: &State) -> RpcResponse {
+        let RpcRequest { id, method, params } = match serde_json::from_value(request) {
+            Ok(request) => request,
+            Err(err) => {
+                return RpcResponse::new(
+                    Value::Null,
+                    Err(RpcError::new(INVALID_REQUEST, err.to_string())),
+                )
+            }
+        };
+        let result = self.dispatch(&method, &params, state).await;
+        RpcResponse::new(id, result)
+    }
+
+    async fn dispatch(
+        &self,
+        method: &str,
+        params: &[Value],
+        state: &State,
+    ) -> Result<Value, RpcError> {
+        let chain_id = state.chain_id();
+        match method {
+            "eth_chainId" => Ok(to_json(U64::from(chain_id))),
+            "net_version" => Ok(to_json(chain_id.to_string())),
+            "eth_blockNumber" => Ok(to_json(U64::from(state.block_height()))),
+            "eth_gasPrice" => Ok(to_json(U256::zero())),
+            "eth_estimateGas" => Ok(to_json(U64::from(TRANSFER_GAS))),
+            "eth_getBalance" => {
+                let address: Address = param(params, 0)?;
+                Ok(to_json(U256::from(state.get_balance(&address))))
+            }
+            "eth_getTransactionCount" => {
+                let address: Address = param(params, 0)?;
+                Ok(to_json(U256::from(state.get_nonce(&address))))
+            }
+            "eth_sendRawTransaction" => {
+                let rlp: Bytes = param(params, 0)?;
+                self.send_raw_transaction(rlp, state).await
+            }
+            "eth_getTransactionReceipt" => {
+                let hash: H256 = param(params, 0)?;
+                self.transaction_receipt(hash).await
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Method {method} is not supported by the Example Rollup"),
+            )),
+        }
+    }
+
+    /// Translates a signed Ethereum transfer into a rollup transaction and submits it like
+    /// `rollup/submit` would. Validation rejects transfers not signed for the rollup's chain ID.
+    async fn send_raw_transaction(&self, rlp: Bytes, state: &State) -> Result<Value, RpcError> {
+        let transaction = SignedTransaction::from_ethereum_rlp(rlp).map_err(RpcError::rejected)?;
+        let hash = transaction
+            .ethereum_hash()
+            .expect("Transaction is translated from Ethereum");
+        let sender = validate_transaction(state, &transaction).map_err(RpcError::rejected)?;
+        submit_or_hold_transaction(
+            self.sequencer_url.clone(),
+            state,
+            &self.mempool,
+            sender,
+            transaction,
+        )
+        .await
+        .map_err(|err| {
+            let code = match err.status {
+                status if status.is_client_error() => TRANSACTION_REJECTED,
+                StatusCode::ServiceUnavailable => LIMIT_EXCEEDED,
+                _ => INTERNAL_ERROR,
+            };
+            RpcError::new(code, err.message)
+        })?;
+        Ok(to_json(hash))
+    }
+
+    /// Builds a receipt for an Ethereum transaction from the block in which it was executed, or
+    /// returns `null` if it has not been executed yet, or only in blocks which are no longer
+    /// retained.
+    ///
+    /// A transaction which was executed but rejected has status 0.
+    async fn transaction_receipt(&self, hash: H256) -> Result<Value, RpcError> {
+        let Some(entry) = self.block_store.read().await.ethereum_transaction(&hash) else {
+            return Ok(Value::Null);
+        };
+        let executed = entry.transaction;
+        let status = u64::from(executed.error.is_none());
+        Ok(to_json(TransactionReceipt {
+            transaction_hash: hash,
+            transaction_index: (entry.index as u64).into(),
+            from: executed.sender.unwrap_or_default(),
+            to: executed
+                .transaction
+                .map(|transaction| transaction.transaction.destination),
+            block_number: Some(entry.height.into()),
+            status: Some(status.into()),
+            gas_used: Some(TRANSFER_GAS.into()),
+            cumulative_gas_used: TRANSFER_GAS.into(),
+            effective_gas_price: Some(U256::zero()),
+            ..Default::default()
+        }))
+    }
+}
+
+/// Defines the Ethereum JSON-RPC API, which is served alongside the rollup API.
+///
+/// # Parameters
+/// - `sequencer_url`: The sequencer API endpoint URL, to which transactions are forwarded.
+/// - `mempool`: The pool of future-nonce transactions shared with the rollup API.
+/// - `block_store`: The record of executed blocks, from which receipts are served.
+pub(crate) fn define_api(
+    sequencer_url: Url,
+    mempool: Arc<RwLock<Mempool>>,
+    block_store: Arc<RwLock<BlockStore>>,
+) -> io::Result<Api<Arc<RwLock<State>>, ServerError>> {
+    let error_mapper = |err| io::Error::new(io::ErrorKind::Other, err);
+    let toml = toml::from_str::<toml::Value>(include_str!("rpc.toml"))
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    let mut api = Api::<Arc<RwLock<State>>, ServerError>::new(toml).map_err(error_mapper)?;
+    let context = RpcContext {
+        sequencer_url,
+        mempool,
+        block_store,
+    };
+
+    // JSON-RPC errors are reported in the response body, so the handler itself never fails.
+    api.post("rpc", move |req, state| {
+        let context = context.clone();
+        async move {
+            let response = match req.body_json::<Value>() {
+                Ok(body) => context.handle(body, state).await,
+                Err(err) => to_json(RpcResponse::new(
+                    Value::Null,
+                    Err(RpcError::new(PARSE_ERROR, err.to_string())),
+                )),
+            };
+            Ok(response)
+        }
+        .boxed()
+    })
+    .map_err(error_mapper)?;
+
+    Ok(api)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{serve, APIOptions};
+    use crate::blocks::{ExecutedBlock, ExecutedTransaction};
+    use crate::client::RollupApiClient;
+    use crate::RollupVM;
+    use async_std::task::spawn;
+    use commit::Committable;
+    use ethers::{
+        providers::{Http, Middleware, Provider},
+        signers::{LocalWallet, Signer},
+        types::{transaction::eip2718::TypedTransaction, TransactionRequest},
+    };
+    use portpicker::pick_unused_port;
+    use sequencer_utils::u256_to_commitment;
+
+    #[async_std::test]
+    async fn rpc_query_test() {
+        let mut rng = rand::thread_rng();
+        let genesis_wallet = LocalWallet::new(&mut rng);
+        let vm = RollupVM::new(1.into());
+        let genesis_address = genesis_wallet.address();
+        let state = State::from_initial_balances([(genesis_address, 9999)], vm).with_chain_id(7);
+
+        // An applied and a rejected transfer signed by an Ethereum wallet
+        let mut executed = vec![];
+        let mut hashes = vec![];
+        for (nonce, error) in [(0, None), (5, Some(RollupError::SignatureError))] {
+            let request: TypedTransaction = TransactionRequest::new()
+                .to(Address::zero())
+                .value(1)
+                .nonce(nonce)
+                .chain_id(7)
+                .into();
+            let wallet = genesis_wallet.clone().with_chain_id(7u64);
+            let signature = wallet.sign_transaction(&request).await.unwrap();
+            let transaction =
+                SignedTransaction::from_ethereum_rlp(request.rlp_signed(&signature)).unwrap();
+            hashes.push(transaction.ethereum_hash().unwrap());
+            executed.push(ExecutedTransaction {
+                transaction: Some(transaction),
+                sender: Some(genesis_address),
+                error,
+            });
+        }
+        let block_store = Arc::new(RwLock::new(BlockStore::default()));
+        block_store.write().await.insert(ExecutedBlock {
+            height: 3,
+            block: u256_to_commitment(U256::zero()).unwrap(),
+            old_state: state.commit(),
+            new_state: state.commit(),
+            transactions: executed,
+            verified: false,
+        });
+
+        let state = Arc::new(RwLock::new(state));
+        let port = pick_unused_port().expect("No ports free");
+        let api_url: Url = format!("http://localhost:{port}").parse().unwrap();
+        let client = RollupApiClient::new(api_url.clone());
+        let options = APIOptions {
+            api_port: port,
+            sequencer_url: api_url,
+            executor_stream: None,
+            block_store,
+            status_options: None,
+        };
+
+        spawn(async move { serve(&options, state).await });
+
+        client.connect(None).await;
+
+        // Query the rollup through an ordinary Ethereum provider
+        let provider =
+            Provider::<Http>::try_from(format!("http://localhost:{port}/eth/rpc")).unwrap();
+        assert_eq!(provider.get_chainid().await.unwrap(), U256::from(7));
+        assert_eq!(
+            provider.get_balance(genesis_address, None).await.unwrap(),
+            U256::from(9999)
+        );
+        assert_eq!(
+            provider
+                .get_transaction_count(genesis_address, None)
+                .await
+                .unwrap(),
+            U256::zero()
+        );
+
+        // Receipts report the block and outcome of each transaction
+        let receipt = provider
+            .get_transaction_receipt(hashes[0])
+            .await
+            .unwrap()
+            .expect("Applied transaction has a receipt");
+        assert_eq!(receipt.status, Some(1u64.into()));
+        assert_eq!(receipt.block_number, Some(3u64.into()));
+        assert_eq!(receipt.transaction_index, 0u64.into());
+        assert_eq!(receipt.from, genesis_address);
+        let receipt = provider
+            .get_transaction_receipt(hashes[1])
+            .await
+            .unwrap()
+            .expect("Rejected transaction has a receipt");
+        assert_eq!(receipt.status, Some(0u64.into()));
+        assert_eq!(receipt.transaction_index, 1u64.into());
+
+        // Transactions which have not been executed have no receipt yet
+        assert!(provider
+            .get_transaction_receipt(H256::zero())
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
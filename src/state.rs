@@ -11,7 +11,11 @@ use sequencer::{NMTRoot, NamespaceProofType, Vm};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
+use crate::blocks::{ExecutedBlock, ExecutedTransaction};
 use crate::error::RollupError;
+use crate::genesis::DEFAULT_CHAIN_ID;
+use crate::metrics::metrics;
+use crate::multisig::{Multisig, MultisigOp};
 use crate::prover::Proof;
 use crate::transaction::SignedTransaction;
 use crate::RollupVM;
@@ -23,6 +27,8 @@ pub type Nonce = u64;
 pub struct Account {
     balance: Amount,
     nonce: Nonce,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    multisig: Option<Multisig>,
 }
 
 #[derive(Debug, Clone)]
@@ -35,9 +41,24 @@ pub struct State {
     accounts: BTreeMap<Address, Account>,
     nmt_comm: Option<Commitment<NMTRoot>>, // Commitment to the most recent transaction NMT
     prev_state_commitment: Option<Commitment<State>>, // Previous state commitment, used to create a chain linking state committments
+    block_height: u64, // Height of the HotShot block most recently executed, used to enforce transaction validity windows
+    total_supply: Amount, // Sum of all balances, which transactions must conserve
+    chain_id: u64,     // EIP-155 chain ID for which Ethereum transactions must be signed
     pub(crate) vm: RollupVM,
 }
 
+/// The parts of a `State` which change as blocks are executed. Together with the genesis, which
+/// fixes the VM and chain ID, a snapshot restores the state, so that blocks can be executed from
+/// the middle of the chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    accounts: BTreeMap<Address, Account>,
+    nmt_comm: Option<Commitment<NMTRoot>>,
+    prev_state_commitment: Option<Commitment<State>>,
+    block_height: u64,
+    total_supply: Amount,
+}
+
 impl Committable for State {
     /// Commits the current state by creating a serialized commitment object.
     ///
@@ -46,6 +67,9 @@ impl Committable for State {
     /// - Serialized account data
     /// - Block hash of the current state
     /// - Previous state commitments
+    /// - The height of the most recently executed block
+    /// - The total supply
+    /// - The Ethereum chain ID
     /// - The VM ID used in the state.
     fn commit(&self) -> Commitment<State> {
         let serialized_accounts =
@@ -71,6 +95,9 @@ impl Committable for State {
                     .collect::<Vec<_>>(),
             )
             .var_size_field("accounts", serialized_accounts.as_bytes())
+            .u64_field("block_height", self.block_height)
+            .u64_field("total_supply", self.total_supply)
+            .u64_field("chain_id", self.chain_id)
             .u64_field("VM ID", self.vm.id().into())
             .finalize()
     }
@@ -85,6 +112,9 @@ impl State {
     ///
     /// # Returns
     /// A new instance of `State` containing the initialized accounts.
+    ///
+    /// # Panics
+    /// If the initial balances add up to more than `Amount::MAX`.
     pub fn from_initial_balances(
         initial_balances: impl IntoIterator<Item = (Address, Amount)>,
         vm: RollupVM,
@@ -96,13 +126,60 @@ impl State {
                 Account {
                     balance: amount,
                     nonce: 0,
+                    multisig: None,
                 },
             );
         }
+        let total_supply = accounts
+            .values()
+            .try_fold(0, |supply: Amount, account| {
+                supply.checked_add(account.balance)
+            })
+            .expect("Initial balances overflow the total supply");
         State {
             accounts,
             nmt_comm: None,
             prev_state_commitment: None,
+            block_height: 0,
+            total_supply,
+            chain_id: DEFAULT_CHAIN_ID,
+            vm,
+        }
+    }
+
+    /// Set the chain ID for which Ethereum transactions must be signed, `DEFAULT_CHAIN_ID` unless
+    /// set.
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    /// The EIP-155 chain ID for which Ethereum transactions must be signed.
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    /// Take a snapshot of the state, to restore it later with `from_snapshot`.
+    pub fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            accounts: self.accounts.clone(),
+            nmt_comm: self.nmt_comm,
+            prev_state_commitment: self.prev_state_commitment,
+            block_height: self.block_height,
+            total_supply: self.total_supply,
+        }
+    }
+
+    /// Restore a state from a snapshot. The chain ID is `DEFAULT_CHAIN_ID` unless set with
+    /// `with_chain_id`.
+    pub fn from_snapshot(snapshot: StateSnapshot, vm: RollupVM) -> Self {
+        State {
+            accounts: snapshot.accounts,
+            nmt_comm: snapshot.nmt_comm,
+            prev_state_commitment: snapshot.prev_state_commitment,
+            block_height: snapshot.block_height,
+            total_supply: snapshot.total_supply,
+            chain_id: DEFAULT_CHAIN_ID,
             vm,
         }
     }
@@ -110,54 +187,155 @@ impl State {
     /// If the transaction is valid, transition the state and return the new state with updated balances.
     ///
     /// A transaction is valid iff
-    /// 1) The signature on the transaction
-    /// 2) The nonce of the transaction is greater than the sender nonce (this prevent replay attacks)
-    /// 3) The sender has a high enough balance to cover the transfer amount
+    /// 1) The signature on the transaction, which for a transaction translated from Ethereum must
+    ///    be signed for the rollup's chain ID
+    /// 2) The current block height is within the transaction's validity window
+    /// 3) The transaction makes at most `MAX_RECIPIENTS` transfers
+    /// 4) The signers are authorized to send from the sender account (see `authorize`)
+    /// 5) The nonce of the transaction is greater than the sender nonce (this prevent replay attacks)
+    /// 6) The sender has a high enough balance to cover the total amount of all transfers
+    /// 7) Neither the sender nonce nor any destination balance overflows
+    /// 8) Any multisig operation has valid owners
+    ///
+    /// The transfers of a batch transaction and any multisig operation are applied atomically: if
+    /// any check fails, the state is left unchanged.
     pub fn apply_transaction(
         &mut self,
         transaction: &SignedTransaction,
     ) -> Result<(), RollupError> {
         // 1)
         let sender = transaction.recover()?;
-        let destination = transaction.transaction.destination;
+        transaction.check_chain_id(self.chain_id)?;
+
+        // 2)
+        if !transaction.transaction.is_valid_at(self.block_height) {
+            return Err(RollupError::OutsideValidityWindow {
+                height: self.block_height,
+                valid_after: transaction.transaction.valid_after,
+                valid_until: transaction.transaction.valid_until,
+            });
+        }
+
+        // 3)
+        transaction.transaction.check_recipients()?;
+
         let next_nonce = transaction.transaction.nonce;
-        let transfer_amount = transaction.transaction.amount;
         // Fetch the sender's account and check if it exists
         let Account {
             nonce: prev_nonce,
             balance: sender_balance,
+            ..
         } = self
             .accounts
-            .get_mut(&sender)
-            .ok_or(RollupError::InsufficientBalance { address: sender })?;
+            .get(&sender)
+            .cloned()
+            .ok_or(RollupError::UnknownAccount { address: sender })?;
+
+        // 4)
+        self.authorize(sender, transaction)?;
 
         // Validate nonce
-        if next_nonce != *prev_nonce + 1 {
+        let expected_nonce = prev_nonce
+            .checked_add(1)
+            .ok_or(RollupError::Overflow { address: sender })?;
+        if next_nonce != expected_nonce {
             return Err(RollupError::InvalidNonce {
                 address: sender,
-                expected: *prev_nonce + 1,
+                expected: expected_nonce,
                 actual: next_nonce,
             });
         }
 
-        // Validate balance
-        if transfer_amount > *sender_balance {
-            return Err(RollupError::InsufficientBalance { address: sender });
+        // Validate balance. A total which overflows exceeds any balance.
+        let sender_balance = transaction
+            .transaction
+            .total_amount()
+            .and_then(|total| sender_balance.checked_sub(total))
+            .ok_or(RollupError::InsufficientBalance { address: sender })?;
+        let mut balances = BTreeMap::from([(sender, sender_balance)]);
+        for transfer in transaction.transaction.transfers() {
+            let destination = transfer.destination;
+            let balance = balances
+                .entry(destination)
+                .or_insert_with(|| self.get_balance(&destination));
+            *balance = balance
+                .checked_add(transfer.amount)
+                .ok_or(RollupError::Overflow {
+                    address: destination,
+                })?;
         }
 
+        // Validate the multisig operation
+        let multisig_update = match &transaction.transaction.multisig_op {
+            None => None,
+            Some(MultisigOp::Create(multisig)) => {
+                multisig.validate()?;
+                let address = Multisig::address(sender, next_nonce);
+                // Unreachable without a hash collision, since each nonce is used once
+                if self.get_multisig(&address).is_some() {
+                    return Err(RollupError::AccountExists { address });
+                }
+                Some((address, multisig.clone()))
+            }
+            // Only multisig accounts are authorized to set their owners
+            Some(MultisigOp::SetOwners(multisig)) => {
+                multisig.validate()?;
+                Some((sender, multisig.clone()))
+            }
+        };
+
         // Transaction is valid, return the updated state
-        *sender_balance -= transfer_amount;
-        *prev_nonce = next_nonce;
-        let Account {
-            balance: destination_balance,
-            ..
-        } = self.accounts.entry(destination).or_default();
-        *destination_balance += transfer_amount;
+        for (address, balance) in balances {
+            self.accounts.entry(address).or_default().balance = balance;
+        }
+        if let Some((address, multisig)) = multisig_update {
+            self.accounts.entry(address).or_default().multisig = Some(multisig);
+        }
+        self.accounts
+            .get_mut(&sender)
+            .expect("Sender account exists")
+            .nonce = next_nonce;
 
         tracing::info!("Applied transaction {next_nonce} for {sender}");
         Ok(())
     }
 
+    /// Check that the signers of `transaction` are authorized to send it from the `sender` account.
+    ///
+    /// Transactions from a multisig account must be signed by at least `threshold` of its owners,
+    /// and only multisig accounts can set their owners.
+    pub fn authorize(
+        &self,
+        sender: Address,
+        transaction: &SignedTransaction,
+    ) -> Result<(), RollupError> {
+        let account = self
+            .accounts
+            .get(&sender)
+            .ok_or(RollupError::UnknownAccount { address: sender })?;
+        if transaction.transaction.multisig.is_none() {
+            if let Some(MultisigOp::SetOwners(_)) = transaction.transaction.multisig_op {
+                return Err(RollupError::NotMultisig { address: sender });
+            }
+            return Ok(());
+        }
+        account
+            .multisig
+            .as_ref()
+            .ok_or(RollupError::NotMultisig { address: sender })?
+            .authorize(sender, &transaction.signers()?)
+    }
+
+    /// Fetch the owners of a multisig account, or `None` if `address` is not a multisig account.
+    pub fn get_multisig(&self, address: &Address) -> Option<&Multisig> {
+        self.accounts.get(address)?.multisig.as_ref()
+    }
+
+    /// Whether `address` has an account, even with a zero balance.
+    pub fn has_account(&self, address: &Address) -> bool {
+        self.accounts.contains_key(address)
+    }
+
     /// Fetch the balance of an address
     pub fn get_balance(&self, address: &Address) -> Amount {
         self.accounts
@@ -166,6 +344,11 @@ impl State {
             .unwrap_or(0)
     }
 
+    /// Fetch the total supply, the sum of all balances
+    pub fn total_supply(&self) -> Amount {
+        self.total_supply
+    }
+
     /// Fetch the nonce of an address
     pub fn get_nonce(&self, address: &Address) -> Nonce {
         self.accounts
@@ -174,48 +357,104 @@ impl State {
             .unwrap_or(0)
     }
 
+    /// Iterate over the address, balance and nonce of each account.
+    pub fn accounts(&self) -> impl Iterator<Item = (Address, Amount, Nonce)> + '_ {
+        self.accounts
+            .iter()
+            .map(|(address, account)| (*address, account.balance, account.nonce))
+    }
+
+    /// Fetch the total number of transactions applied to the state.
+    ///
+    /// Every applied transaction increments the nonce of its sender by one, so this is the sum of
+    /// all account nonces.
+    pub fn transaction_count(&self) -> u64 {
+        self.accounts.values().map(|account| account.nonce).sum()
+    }
+
+    /// Fetch the height of the most recently executed block
+    pub fn block_height(&self) -> u64 {
+        self.block_height
+    }
+
     /// Execute a block of transactions, updating the state and generating a proof.
     ///
     /// # Parameters
+    /// - `block_height`: The HotShot height of this block.
     /// - `nmt_root`: The root of the NMT for this block.
     /// - `namespace_proof`: Proofs related to the namespace.
     ///
     /// # Returns
-    /// A `Proof` object representing the state after executing the block.
+    /// A `Proof` object representing the state after executing the block, and a record of the
+    /// executed block including the outcome of each transaction.
     pub(crate) async fn execute_block(
         &mut self,
+        block_height: u64,
         nmt_root: NMTRoot,
         namespace_proof: NamespaceProofType,
-    ) -> Proof {
+    ) -> (Proof, ExecutedBlock) {
         let state_commitment = self.commit();
+        self.block_height = block_height;
         let transactions = namespace_proof.get_namespace_leaves();
+        let mut executed_transactions = vec![];
         for txn in transactions {
             if let Some(rollup_txn) = txn.as_vm(&self.vm) {
                 let res = self.apply_transaction(&rollup_txn);
-                if let Err(err) = res {
-                    tracing::error!("Transaction invalid: {}", err)
+                match &res {
+                    Ok(()) => metrics().transactions_applied.inc(),
+                    Err(err) => {
+                        tracing::error!("Transaction invalid: {}", err);
+                        metrics().reject_transaction(err);
+                    }
                 }
+                executed_transactions.push(ExecutedTransaction {
+                    sender: rollup_txn.recover().ok(),
+                    transaction: Some(rollup_txn),
+                    error: res.err(),
+                });
             } else {
-                tracing::error!("NMT transaction is malformed")
+                // Leaves come from this VM's namespace, so only the decoding can fail
+                let error = RollupError::MalformedTransaction;
+                tracing::error!("NMT transaction is malformed: {}", error);
+                metrics().malformed_leaves.inc();
+                executed_transactions.push(ExecutedTransaction {
+                    transaction: None,
+                    sender: None,
+                    error: Some(error),
+                });
             }
         }
         self.nmt_comm = Some(nmt_root.commit());
         self.prev_state_commitment = Some(state_commitment);
 
-        Proof::generate(
+        let executed_block = ExecutedBlock {
+            height: block_height,
+            block: nmt_root.commit(),
+            old_state: state_commitment,
+            new_state: self.commit(),
+            transactions: executed_transactions,
+            verified: false,
+        };
+        let proof = Proof::generate(
             nmt_root,
             self.commit(),
             self.prev_state_commitment.unwrap(),
             namespace_proof,
             &self.vm,
-        )
+        );
+        (proof, executed_block)
     }
 }
 #[cfg(test)]
 mod tests {
-    use crate::transaction::Transaction;
+    use crate::testing::mock_block;
+    use crate::transaction::{Recipient, Transaction, MAX_RECIPIENTS};
 
     use ethers::signers::{LocalWallet, Signer};
+    use ethers::types::{transaction::eip2718::TypedTransaction, TransactionRequest};
+    use proptest::prelude::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use super::*;
     #[async_std::test]
@@ -230,6 +469,11 @@ mod tests {
             amount: 110,
             destination: bob.address(),
             nonce: 1,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
         };
 
         // Try to overspend
@@ -266,5 +510,685 @@ mod tests {
                 actual: 1,
             }
         );
+
+        // A sender without an account is distinguished from an overspend
+        let carol = LocalWallet::new(&mut rng);
+        signed_transaction =
+            SignedTransaction::new(signed_transaction.transaction.clone(), &carol).await;
+        let err = state
+            .apply_transaction(&signed_transaction)
+            .expect_err("Transaction from an unknown account should throw error.");
+        assert_eq!(
+            err,
+            RollupError::UnknownAccount {
+                address: carol.address()
+            }
+        );
+    }
+
+    #[async_std::test]
+    async fn validity_window_test() {
+        let mut rng = rand::thread_rng();
+        let vm = RollupVM::new(1.into());
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+        let mut state = State::from_initial_balances([(alice.address(), 100)], vm);
+        state.block_height = 10;
+        let mut transaction = Transaction {
+            amount: 10,
+            destination: bob.address(),
+            nonce: 1,
+            valid_after: Some(11),
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
+        };
+
+        // Not yet valid, including at exactly `valid_after`
+        for valid_after in [11, 10] {
+            transaction.valid_after = Some(valid_after);
+            let signed_transaction = SignedTransaction::new(transaction.clone(), &alice).await;
+            assert_eq!(
+                state.apply_transaction(&signed_transaction),
+                Err(RollupError::OutsideValidityWindow {
+                    height: 10,
+                    valid_after: Some(valid_after),
+                    valid_until: None,
+                })
+            );
+        }
+
+        // Expired
+        transaction.valid_after = None;
+        transaction.valid_until = Some(9);
+        let signed_transaction = SignedTransaction::new(transaction.clone(), &alice).await;
+        let err = state
+            .apply_transaction(&signed_transaction)
+            .expect_err("Expired transaction should throw error.");
+        assert_eq!(
+            err,
+            RollupError::OutsideValidityWindow {
+                height: 10,
+                valid_after: None,
+                valid_until: Some(9),
+            }
+        );
+        assert_eq!(state.get_nonce(&alice.address()), 0);
+
+        // Inside the window
+        transaction.valid_after = Some(9);
+        transaction.valid_until = Some(10);
+        let signed_transaction = SignedTransaction::new(transaction, &alice).await;
+        state
+            .apply_transaction(&signed_transaction)
+            .expect("Valid transaction should transition state");
+        assert_eq!(state.get_balance(&bob.address()), 10);
+    }
+
+    #[async_std::test]
+    async fn test_arithmetic_edge_cases() {
+        let vm = RollupVM::new(1.into());
+        let alice = LocalWallet::new(&mut ChaChaRng::seed_from_u64(0));
+        let bob = LocalWallet::new(&mut ChaChaRng::seed_from_u64(1));
+        let transfer = |amount, nonce| Transaction {
+            amount,
+            destination: bob.address(),
+            nonce,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
+        };
+
+        // The whole supply can be held by a single account, and transferred at once
+        let mut state = State::from_initial_balances([(alice.address(), Amount::MAX)], vm);
+        assert_eq!(state.total_supply(), Amount::MAX);
+        let txn = SignedTransaction::new(transfer(Amount::MAX, 1), &alice).await;
+        state.apply_transaction(&txn).unwrap();
+        assert_eq!(state.get_balance(&alice.address()), 0);
+        assert_eq!(state.get_balance(&bob.address()), Amount::MAX);
+        assert_eq!(state.total_supply(), Amount::MAX);
+
+        // Transfers of zero and to oneself leave balances unchanged
+        let mut state = State::from_initial_balances([(alice.address(), 100)], vm);
+        let txn = SignedTransaction::new(transfer(0, 1), &alice).await;
+        state.apply_transaction(&txn).unwrap();
+        let mut to_self = transfer(100, 2);
+        to_self.destination = alice.address();
+        let txn = SignedTransaction::new(to_self, &alice).await;
+        state.apply_transaction(&txn).unwrap();
+        assert_eq!(state.get_balance(&alice.address()), 100);
+        assert_eq!(state.get_balance(&bob.address()), 0);
+        assert_eq!(state.get_nonce(&alice.address()), 2);
+
+        // Overspending by one is rejected
+        let txn = SignedTransaction::new(transfer(101, 3), &alice).await;
+        assert_eq!(
+            state.apply_transaction(&txn),
+            Err(RollupError::InsufficientBalance {
+                address: alice.address()
+            })
+        );
+
+        // A destination balance which would overflow is rejected, leaving the state unchanged.
+        // This is only reachable if the supply accounting is broken, so break it on purpose.
+        state.accounts.entry(bob.address()).or_default().balance = Amount::MAX;
+        let commitment = state.commit();
+        let txn = SignedTransaction::new(transfer(1, 3), &alice).await;
+        assert_eq!(
+            state.apply_transaction(&txn),
+            Err(RollupError::Overflow {
+                address: bob.address()
+            })
+        );
+        assert_eq!(state.commit(), commitment);
+
+        // So is a sender nonce which would overflow
+        state.accounts.get_mut(&alice.address()).unwrap().nonce = Nonce::MAX;
+        let txn = SignedTransaction::new(transfer(1, 0), &alice).await;
+        assert_eq!(
+            state.apply_transaction(&txn),
+            Err(RollupError::Overflow {
+                address: alice.address()
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Initial balances overflow the total supply")]
+    fn test_initial_supply_overflow() {
+        State::from_initial_balances(
+            [
+                (Address::repeat_byte(1), Amount::MAX),
+                (Address::repeat_byte(2), 1),
+            ],
+            RollupVM::new(1.into()),
+        );
+    }
+
+    #[async_std::test]
+    async fn test_supply_is_conserved() {
+        let sender = LocalWallet::new(&mut ChaChaRng::seed_from_u64(0));
+        let receiver = Address::repeat_byte(1);
+        let mut state = State::from_initial_balances(
+            [(sender.address(), 100), (receiver, 50)],
+            RollupVM::new(1.into()),
+        );
+        assert_eq!(state.total_supply(), 150);
+
+        // Applied and rejected transfers leave the supply unchanged
+        for (amount, nonce) in [(30, 1), (1000, 2)] {
+            let transaction = Transaction {
+                amount,
+                destination: receiver,
+                nonce,
+                valid_after: None,
+                valid_until: None,
+                recipients: vec![],
+                multisig: None,
+                multisig_op: None,
+            };
+            let result =
+                state.apply_transaction(&SignedTransaction::new(transaction, &sender).await);
+            assert_eq!(result.is_ok(), amount == 30);
+            assert_eq!(state.total_supply(), 150);
+        }
+        assert_eq!(state.get_balance(&sender.address()), 70);
+        assert_eq!(state.get_balance(&receiver), 80);
+    }
+
+    #[async_std::test]
+    async fn test_snapshot() {
+        let vm = RollupVM::new(1.into());
+        let sender = LocalWallet::new(&mut ChaChaRng::seed_from_u64(0));
+        let mut state = State::from_initial_balances([(sender.address(), 100)], vm)
+            .with_chain_id(DEFAULT_CHAIN_ID + 1);
+        let mut blocks = vec![];
+        for nonce in [1, 2] {
+            let transaction = Transaction {
+                amount: 10,
+                destination: Address::repeat_byte(1),
+                nonce,
+                valid_after: None,
+                valid_until: None,
+                recipients: vec![],
+                multisig: None,
+                multisig_op: None,
+            };
+            let leaf = vm.wrap(&SignedTransaction::new(transaction, &sender).await);
+            blocks.push(mock_block(&[leaf], vm.id()));
+        }
+        let (header, proof) = blocks[0].clone();
+        state
+            .execute_block(0, header.transactions_root, proof)
+            .await;
+
+        // A restored snapshot has the same commitment, and executes blocks alike
+        let snapshot = serde_json::to_string(&state.snapshot()).unwrap();
+        let mut restored = State::from_snapshot(serde_json::from_str(&snapshot).unwrap(), vm)
+            .with_chain_id(DEFAULT_CHAIN_ID + 1);
+        assert_eq!(restored.commit(), state.commit());
+        assert_eq!(restored.block_height(), 0);
+        assert_eq!(restored.total_supply(), 100);
+        assert_eq!(restored.get_nonce(&sender.address()), 1);
+
+        let (header, proof) = blocks[1].clone();
+        state
+            .execute_block(1, header.transactions_root.clone(), proof.clone())
+            .await;
+        restored
+            .execute_block(1, header.transactions_root, proof)
+            .await;
+        assert_eq!(restored.commit(), state.commit());
+        assert_eq!(restored.get_balance(&Address::repeat_byte(1)), 20);
+
+        // The chain ID is not part of the snapshot, but is committed
+        let restored = State::from_snapshot(state.snapshot(), vm);
+        assert_ne!(restored.commit(), state.commit());
+    }
+
+    #[test]
+    fn test_supply_is_committed() {
+        let state = State::from_initial_balances([(Address::zero(), 100)], RollupVM::new(1.into()));
+        let mut minted = state.clone();
+        minted.total_supply += 1;
+        assert_ne!(state.commit(), minted.commit());
+    }
+
+    #[async_std::test]
+    async fn test_batch_transfer() {
+        let wallets: Vec<LocalWallet> = (0..3)
+            .map(|i| LocalWallet::new(&mut ChaChaRng::seed_from_u64(i)))
+            .collect();
+        let [alice, bob, carol] = [0, 1, 2].map(|i| wallets[i].address());
+        let mut state = State::from_initial_balances([(alice, 100)], RollupVM::new(1.into()));
+        let batch = |payments: &[(Address, Amount)], nonce| {
+            let recipients = payments
+                .iter()
+                .map(|&(destination, amount)| Recipient {
+                    destination,
+                    amount,
+                })
+                .collect();
+            Transaction::batch(recipients, nonce).unwrap()
+        };
+        assert!(Transaction::batch(vec![], 1).is_none());
+
+        // All recipients are paid under a single nonce, including the sender itself
+        let txn = batch(&[(bob, 10), (carol, 20), (alice, 5), (bob, 1)], 1);
+        assert_eq!(txn.total_amount(), Some(36));
+        let txn = SignedTransaction::new(txn, &wallets[0]).await;
+        state.apply_transaction(&txn).unwrap();
+        assert_eq!(state.get_balance(&alice), 69);
+        assert_eq!(state.get_balance(&bob), 11);
+        assert_eq!(state.get_balance(&carol), 20);
+        assert_eq!(state.get_nonce(&alice), 1);
+        assert_eq!(state.total_supply(), 100);
+
+        // The total is checked against the balance, even if each amount alone is affordable
+        let before = state.clone();
+        let txn = SignedTransaction::new(batch(&[(bob, 40), (carol, 40)], 2), &wallets[0]).await;
+        assert_eq!(
+            state.apply_transaction(&txn),
+            Err(RollupError::InsufficientBalance { address: alice })
+        );
+        // A total which overflows is an overspend
+        let txn = batch(&[(bob, Amount::MAX), (carol, 1)], 2);
+        assert_eq!(txn.total_amount(), None);
+        let txn = SignedTransaction::new(txn, &wallets[0]).await;
+        assert_eq!(
+            state.apply_transaction(&txn),
+            Err(RollupError::InsufficientBalance { address: alice })
+        );
+        // A failing transfer reverts the transfers before it
+        state.accounts.entry(carol).or_default().balance = Amount::MAX;
+        let before_overflow = state.clone();
+        let txn = SignedTransaction::new(batch(&[(bob, 1), (carol, 1)], 2), &wallets[0]).await;
+        assert_eq!(
+            state.apply_transaction(&txn),
+            Err(RollupError::Overflow { address: carol })
+        );
+        assert_eq!(state.commit(), before_overflow.commit());
+        state.accounts.entry(carol).or_default().balance = 20;
+        assert_eq!(state.commit(), before.commit());
+
+        // A batch may make at most `MAX_RECIPIENTS` transfers
+        let txn = batch(&[(bob, 0); MAX_RECIPIENTS + 1], 2);
+        let txn = SignedTransaction::new(txn, &wallets[0]).await;
+        assert_eq!(
+            state.apply_transaction(&txn),
+            Err(RollupError::TooManyRecipients {
+                max: MAX_RECIPIENTS,
+                actual: MAX_RECIPIENTS + 1,
+            })
+        );
+        assert_eq!(state.commit(), before.commit());
+        let txn = batch(&[(bob, 0); MAX_RECIPIENTS], 2);
+        state
+            .apply_transaction(&SignedTransaction::new(txn, &wallets[0]).await)
+            .unwrap();
+
+        // A batch with a single recipient is a simple transfer
+        assert_eq!(
+            batch(&[(bob, 10)], 2),
+            Transaction {
+                amount: 10,
+                destination: bob,
+                nonce: 2,
+                valid_after: None,
+                valid_until: None,
+                recipients: vec![],
+                multisig: None,
+                multisig_op: None,
+            }
+        );
+    }
+
+    #[async_std::test]
+    async fn test_multisig_account() {
+        let wallets: Vec<LocalWallet> = (0..4)
+            .map(|i| LocalWallet::new(&mut ChaChaRng::seed_from_u64(i)))
+            .collect();
+        let [alice, bob, carol, dave] = [0, 1, 2, 3].map(|i| wallets[i].address());
+        let mut state = State::from_initial_balances([(alice, 100)], RollupVM::new(1.into()));
+        let transfer = |amount, destination, nonce| Transaction {
+            amount,
+            destination,
+            nonce,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
+        };
+        let sign = |transaction: Transaction, signers: &[usize]| {
+            let wallets = &wallets;
+            let signers = signers.to_vec();
+            async move {
+                let mut signed = SignedTransaction::new(transaction, &wallets[signers[0]]).await;
+                for &signer in &signers[1..] {
+                    signed.cosign(&wallets[signer]).await;
+                }
+                signed
+            }
+        };
+        let owners = Multisig {
+            owners: vec![alice, bob, carol],
+            threshold: 2,
+        };
+
+        // Alice creates and funds a 2 of 3 multisig account
+        let treasury = Multisig::address(alice, 1);
+        let mut create = transfer(50, treasury, 1);
+        create.multisig_op = Some(MultisigOp::Create(owners.clone()));
+        state.apply_transaction(&sign(create, &[0]).await).unwrap();
+        assert_eq!(state.get_multisig(&treasury), Some(&owners));
+        assert_eq!(state.get_balance(&treasury), 50);
+        assert_eq!(state.get_nonce(&alice), 1);
+
+        // Spending requires two owners
+        let mut spend = transfer(10, dave, 1);
+        spend.multisig = Some(treasury);
+        for signers in [vec![0], vec![0, 0], vec![0, 3]] {
+            let txn = sign(spend.clone(), &signers).await;
+            assert_eq!(txn.recover(), Ok(treasury));
+            assert_eq!(
+                state.apply_transaction(&txn),
+                Err(RollupError::InsufficientSignatures {
+                    address: treasury,
+                    threshold: 2,
+                    signers: 1,
+                })
+            );
+        }
+        state
+            .apply_transaction(&sign(spend, &[2, 1]).await)
+            .unwrap();
+        assert_eq!(state.get_balance(&treasury), 40);
+        assert_eq!(state.get_balance(&dave), 10);
+        assert_eq!(state.get_nonce(&treasury), 1);
+
+        // The owners replace themselves with Dave alone
+        let new_owners = Multisig {
+            owners: vec![dave],
+            threshold: 1,
+        };
+        let mut set_owners = transfer(0, treasury, 2);
+        set_owners.multisig = Some(treasury);
+        set_owners.multisig_op = Some(MultisigOp::SetOwners(new_owners.clone()));
+        state
+            .apply_transaction(&sign(set_owners, &[0, 1]).await)
+            .unwrap();
+        assert_eq!(state.get_multisig(&treasury), Some(&new_owners));
+        let mut spend = transfer(10, alice, 3);
+        spend.multisig = Some(treasury);
+        assert!(matches!(
+            state.apply_transaction(&sign(spend.clone(), &[0, 1]).await),
+            Err(RollupError::InsufficientSignatures { .. })
+        ));
+        state.apply_transaction(&sign(spend, &[3]).await).unwrap();
+        assert_eq!(state.get_balance(&treasury), 30);
+
+        // Ordinary accounts are not multisig accounts
+        let commitment = state.commit();
+        let mut set_owners = transfer(0, alice, 2);
+        set_owners.multisig_op = Some(MultisigOp::SetOwners(new_owners));
+        assert_eq!(
+            state.apply_transaction(&sign(set_owners, &[0]).await),
+            Err(RollupError::NotMultisig { address: alice })
+        );
+        let mut spend = transfer(10, bob, 1);
+        spend.multisig = Some(dave);
+        assert_eq!(
+            state.apply_transaction(&sign(spend, &[3]).await),
+            Err(RollupError::NotMultisig { address: dave })
+        );
+
+        // Invalid owners are rejected
+        let mut create = transfer(0, treasury, 2);
+        create.multisig_op = Some(MultisigOp::Create(Multisig {
+            owners: vec![alice],
+            threshold: 2,
+        }));
+        assert!(matches!(
+            state.apply_transaction(&sign(create, &[0]).await),
+            Err(RollupError::InvalidMultisigOwners { .. })
+        ));
+        assert_eq!(state.commit(), commitment);
+    }
+
+    #[async_std::test]
+    async fn test_ethereum_chain_id() {
+        let vm = RollupVM::new(1.into());
+        let alice = LocalWallet::new(&mut ChaChaRng::seed_from_u64(0));
+        let mut state = State::from_initial_balances([(alice.address(), 100)], vm);
+        let transfer = TransactionRequest::new()
+            .to(Address::repeat_byte(1))
+            .value(10)
+            .nonce(0)
+            .gas(21000)
+            .gas_price(0);
+
+        // The chain ID is not the VM ID
+        assert_eq!(state.chain_id(), DEFAULT_CHAIN_ID);
+
+        // Signed for another chain, such as Ethereum mainnet
+        let request: TypedTransaction = transfer.clone().chain_id(1).into();
+        let signature = alice
+            .clone()
+            .with_chain_id(1u64)
+            .sign_transaction(&request)
+            .await
+            .unwrap();
+        let transaction = SignedTransaction::from_ethereum_rlp(request.rlp_signed(&signature))
+            .expect("Should translate transfer");
+        assert_eq!(
+            state.apply_transaction(&transaction),
+            Err(RollupError::WrongChainId {
+                expected: DEFAULT_CHAIN_ID,
+                actual: Some(1),
+            })
+        );
+
+        // Legacy transaction signed without a chain ID
+        let request: TypedTransaction = transfer.clone().into();
+        let signature = alice.sign_hash(request.sighash()).unwrap();
+        let transaction = SignedTransaction::from_ethereum_rlp(request.rlp_signed(&signature))
+            .expect("Should translate transfer");
+        assert_eq!(transaction.recover(), Ok(alice.address()));
+        assert_eq!(
+            state.apply_transaction(&transaction),
+            Err(RollupError::WrongChainId {
+                expected: DEFAULT_CHAIN_ID,
+                actual: None,
+            })
+        );
+        assert_eq!(state.get_nonce(&alice.address()), 0);
+
+        // Signed for the rollup's chain ID
+        let request: TypedTransaction = transfer.chain_id(DEFAULT_CHAIN_ID).into();
+        let signature = alice
+            .clone()
+            .with_chain_id(DEFAULT_CHAIN_ID)
+            .sign_transaction(&request)
+            .await
+            .unwrap();
+        let transaction = SignedTransaction::from_ethereum_rlp(request.rlp_signed(&signature))
+            .expect("Should translate transfer");
+        state.apply_transaction(&transaction).unwrap();
+        assert_eq!(state.get_nonce(&alice.address()), 1);
+    }
+
+    #[async_std::test]
+    async fn test_malformed_leaf() {
+        let vm = RollupVM::new(1.into());
+        let alice = LocalWallet::new(&mut ChaChaRng::seed_from_u64(0));
+        let mut state = State::from_initial_balances([(alice.address(), 100)], vm);
+        let transaction = Transaction {
+            amount: 10,
+            destination: Address::repeat_byte(1),
+            nonce: 1,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
+        };
+        let transaction = SignedTransaction::new(transaction, &alice).await;
+        let leaves = [
+            sequencer::Transaction::new(vm.id(), vec![0xff]),
+            vm.wrap(&transaction),
+        ];
+        let (header, proof) = mock_block(&leaves, vm.id());
+
+        let (_, block) = state
+            .execute_block(0, header.transactions_root, proof)
+            .await;
+        assert_eq!(block.transactions.len(), 2);
+        assert!(block.transactions[0].transaction.is_none());
+        assert_eq!(
+            block.transactions[0].error,
+            Some(RollupError::MalformedTransaction)
+        );
+        // The malformed leaf does not prevent the rest of the block from executing
+        assert_eq!(block.transactions[1].error, None);
+        assert_eq!(state.get_nonce(&alice.address()), 1);
+    }
+
+    const NUM_ACCOUNTS: usize = 4;
+
+    /// A transfer between the test accounts. The nonce is offset from the sender's next nonce, and
+    /// a destination of `NUM_ACCOUNTS` stands for an address with no account.
+    #[derive(Clone, Debug)]
+    struct Transfer {
+        sender: usize,
+        destination: usize,
+        amount: Amount,
+        nonce_offset: i64,
+    }
+
+    fn transfer() -> impl Strategy<Value = Transfer> {
+        (
+            0..NUM_ACCOUNTS,
+            0..=NUM_ACCOUNTS,
+            0..1500u64,
+            prop_oneof![8 => Just(0i64), 1 => Just(-1i64), 1 => Just(1i64)],
+        )
+            .prop_map(|(sender, destination, amount, nonce_offset)| Transfer {
+                sender,
+                destination,
+                amount,
+                nonce_offset,
+            })
+    }
+
+    fn total_supply(state: &State) -> u128 {
+        state
+            .accounts()
+            .map(|(_, balance, _)| balance as u128)
+            .sum()
+    }
+
+    /// Apply `transfers` to a state funded with `balances`, checking the invariants of
+    /// `apply_transaction` after each one.
+    async fn check_transfers(
+        balances: Vec<Amount>,
+        transfers: Vec<Transfer>,
+    ) -> Result<(), TestCaseError> {
+        let wallets: Vec<LocalWallet> = (0..NUM_ACCOUNTS)
+            .map(|i| LocalWallet::new(&mut ChaChaRng::seed_from_u64(i as u64)))
+            .collect();
+        let unfunded = Address::repeat_byte(0xff);
+        // Accounts without an initial balance do not exist
+        let mut state = State::from_initial_balances(
+            wallets
+                .iter()
+                .zip(&balances)
+                .filter(|(_, balance)| **balance > 0)
+                .map(|(wallet, balance)| (wallet.address(), *balance)),
+            RollupVM::new(1.into()),
+        );
+        let supply = total_supply(&state);
+
+        for transfer in transfers {
+            let sender = wallets[transfer.sender].address();
+            let destination = wallets
+                .get(transfer.destination)
+                .map(|wallet| wallet.address())
+                .unwrap_or(unfunded);
+            let nonce = (state.get_nonce(&sender) as i64 + 1 + transfer.nonce_offset) as Nonce;
+            let transaction = Transaction {
+                amount: transfer.amount,
+                destination,
+                nonce,
+                valid_after: None,
+                valid_until: None,
+                recipients: vec![],
+                multisig: None,
+                multisig_op: None,
+            };
+            let transaction = SignedTransaction::new(transaction, &wallets[transfer.sender]).await;
+
+            let before = state.clone();
+            let result = state.apply_transaction(&transaction);
+            let valid = transfer.nonce_offset == 0
+                && before.has_account(&sender)
+                && transfer.amount <= before.get_balance(&sender);
+            prop_assert_eq!(result.is_ok(), valid, "{:?}: {:?}", transfer, result);
+            if !before.has_account(&sender) {
+                prop_assert_eq!(
+                    result.clone(),
+                    Err(RollupError::UnknownAccount { address: sender })
+                );
+            }
+
+            if result.is_err() {
+                // Rejected transactions leave the state and its commitment unchanged
+                prop_assert_eq!(state.commit(), before.commit());
+                prop_assert_eq!(
+                    state.accounts().collect::<Vec<_>>(),
+                    before.accounts().collect::<Vec<_>>()
+                );
+            } else {
+                // The sender's nonce increases by exactly one, and no other nonce changes
+                for (address, _, nonce) in state.accounts() {
+                    let expected = before.get_nonce(&address) + (address == sender) as Nonce;
+                    prop_assert_eq!(nonce, expected);
+                }
+                if sender != destination {
+                    prop_assert_eq!(
+                        state.get_balance(&sender),
+                        before.get_balance(&sender) - transfer.amount
+                    );
+                    prop_assert_eq!(
+                        state.get_balance(&destination),
+                        before.get_balance(&destination) + transfer.amount
+                    );
+                }
+            }
+
+            // The total supply is conserved, and no balance underflows, which would make it
+            // exceed the total supply
+            prop_assert_eq!(total_supply(&state), supply);
+            prop_assert_eq!(state.total_supply() as u128, supply);
+            for (_, balance, _) in state.accounts() {
+                prop_assert!(balance as u128 <= supply);
+            }
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_apply_transaction_invariants(
+            balances in prop::collection::vec(0..1000u64, NUM_ACCOUNTS),
+            transfers in prop::collection::vec(transfer(), 1..20),
+        ) {
+            async_std::task::block_on(check_transfers(balances, transfers))?;
+        }
     }
 }
@@ -0,0 +1,158 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+use async_std::future::timeout;
+use commit::{Commitment, Committable};
+use contract_bindings::example_rollup::ExampleRollup;
+use ethers::{
+    abi::Address,
+    providers::{Middleware, Provider, Ws},
+    types::U256,
+};
+use futures::{join, Future, FutureExt};
+use hotshot_contract_bindings::hot_shot::HotShot;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use surf_disco::{error::ClientError, Url};
+
+use crate::blocks::BlockStore;
+use crate::state::State;
+use crate::utils::create_provider;
+
+/// How long each connectivity check may take before the dependency is reported as unreachable.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The L1 configuration of the executor, which the API needs to report on the rollup's L1 state.
+#[derive(Clone, Debug)]
+pub struct StatusOptions {
+    pub l1_http_provider: Url,
+    pub l1_ws_provider: Url,
+    pub hotshot_address: Address,
+    pub rollup_address: Address,
+    /// The account that sends proofs to the rollup contract.
+    pub prover_address: Address,
+}
+
+/// The status of the rollup node, served at `rollup/status`.
+///
+/// Values read from L1 are `None` if the L1 is unreachable, or if the API was started without
+/// `StatusOptions`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NodeStatus {
+    /// The HotShot height of the last block executed by the rollup.
+    pub last_executed_block: Option<u64>,
+    /// The commitment to the current, not yet verified, state.
+    pub state_commitment: Commitment<State>,
+    /// The number of HotShot blocks committed to the HotShot contract.
+    pub hotshot_block_height: Option<u64>,
+    /// `ExampleRollup.numVerifiedBlocks`.
+    pub verified_blocks: Option<u64>,
+    /// `ExampleRollup.stateCommitment`.
+    pub verified_state_commitment: Option<U256>,
+    /// The number of executed blocks that have not been verified on L1 yet.
+    pub verification_lag: Option<u64>,
+    /// Unix timestamp, in seconds, of the last successful `verifyBlocks` call.
+    pub last_verification: Option<u64>,
+    /// The L1 balance of the account that sends proofs to the rollup contract.
+    pub prover_balance: Option<U256>,
+    pub sequencer_connected: bool,
+    pub l1_http_connected: bool,
+    pub l1_ws_connected: bool,
+    /// Whether the rollup has executed every block committed to the HotShot contract.
+    pub caught_up: bool,
+}
+
+impl NodeStatus {
+    /// The node is ready if it can reach its dependencies and has caught up with HotShot.
+    pub fn is_ready(&self) -> bool {
+        self.sequencer_connected && self.l1_http_connected && self.l1_ws_connected && self.caught_up
+    }
+}
+
+/// Runs `check`, returning `None` if it fails or takes longer than `CHECK_TIMEOUT`.
+async fn check<T, E>(check: impl Future<Output = Result<T, E>>) -> Option<T> {
+    timeout(CHECK_TIMEOUT, check).await.ok()?.ok()
+}
+
+impl NodeStatus {
+    /// The status known locally, from a snapshot of the rollup state and the recorded blocks.
+    ///
+    /// Values which require contacting the sequencer or L1 are left unset, so that the snapshot can
+    /// be taken without holding any lock across I/O. They are filled in by `node_status`.
+    pub fn local(state: &State, block_store: &BlockStore) -> Self {
+        Self {
+            last_executed_block: block_store.last_executed_block(),
+            state_commitment: state.commit(),
+            hotshot_block_height: None,
+            verified_blocks: None,
+            verified_state_commitment: None,
+            verification_lag: None,
+            last_verification: block_store.last_verification().map(|time| {
+                time.duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()
+            }),
+            prover_balance: None,
+            sequencer_connected: false,
+            l1_http_connected: false,
+            l1_ws_connected: false,
+            caught_up: false,
+        }
+    }
+}
+
+/// Collects the status of the rollup node, by checking its dependencies concurrently.
+///
+/// # Parameters
+/// - `options`: The L1 configuration of the executor, if known.
+/// - `sequencer_url`: The URL of the HotShot sequencer node.
+/// - `status`: The local status, see `NodeStatus::local`.
+pub async fn node_status(
+    options: Option<&StatusOptions>,
+    sequencer_url: &Url,
+    mut status: NodeStatus,
+) -> NodeStatus {
+    let sequencer = surf_disco::Client::<ClientError>::new(sequencer_url.clone());
+    let sequencer_connected = sequencer.connect(Some(CHECK_TIMEOUT));
+    let Some(options) = options else {
+        status.sequencer_connected = sequencer_connected.await;
+        return status;
+    };
+
+    let provider = Arc::new(create_provider(&options.l1_http_provider));
+    let rollup_contract = ExampleRollup::new(options.rollup_address, provider.clone());
+    let hotshot_contract = HotShot::new(options.hotshot_address, provider.clone());
+    let verified_blocks = rollup_contract.num_verified_blocks();
+    let verified_state_commitment = rollup_contract.state_commitment();
+    let hotshot_block_height = hotshot_contract.block_height();
+    (
+        status.sequencer_connected,
+        status.l1_http_connected,
+        status.l1_ws_connected,
+        status.verified_blocks,
+        status.verified_state_commitment,
+        status.hotshot_block_height,
+        status.prover_balance,
+    ) = join!(
+        sequencer_connected,
+        check(provider.get_block_number()).map(|block| block.is_some()),
+        check(Provider::<Ws>::connect(options.l1_ws_provider.clone())).map(|ws| ws.is_some()),
+        check(verified_blocks.call()).map(|blocks| blocks.map(|blocks| blocks.as_u64())),
+        check(verified_state_commitment.call()),
+        check(hotshot_block_height.call()).map(|height| height.map(|height| height.as_u64())),
+        check(provider.get_balance(options.prover_address, None)),
+    );
+
+    let executed_blocks = status.last_executed_block.map_or(0, |height| height + 1);
+    status.verification_lag = status
+        .verified_blocks
+        .map(|verified| executed_blocks.saturating_sub(verified));
+    status.caught_up = status
+        .hotshot_block_height
+        .is_some_and(|height| executed_blocks >= height);
+    status
+}
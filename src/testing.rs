@@ -0,0 +1,239 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the sequencer-example-l2 repository.
+
+// You should have received a copy of the MIT License
+// along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
+
+//! In-memory fakes of L1 and the HotShot query service, for fast and deterministic executor tests.
+//!
+//! Both fakes can be driven and inspected from the test while the executor runs against a clone,
+//! and can be made to fail a given number of requests, to exercise the executor's retry paths.
+
+use async_std::task::sleep;
+use async_trait::async_trait;
+use commit::Committable;
+use contract_bindings::example_rollup;
+use ethers::types::U256;
+use futures::stream::{self, BoxStream, StreamExt};
+use hotshot::traits::Block as _;
+use jf_primitives::merkle_tree::MerkleCommitment;
+use sequencer::{Block, Header, NMTRoot, NamespaceProofType, Transaction, VmId};
+use sequencer_utils::commitment_to_u256;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::l1::{L1Error, NewBlocks, RollupL1};
+use crate::query::{HotShotQuery, QueryError};
+
+/// How often the fakes check for new events and blocks.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Build a HotShot block containing `transactions`, returning its header and the namespace proof
+/// of the rollup with ID `vm`.
+pub fn mock_block(transactions: &[Transaction], vm: VmId) -> (Header, NamespaceProofType) {
+    let block = transactions.iter().fold(Block::new(), |block, txn| {
+        block
+            .add_transaction_raw(txn)
+            .expect("Error adding transaction to block")
+    });
+    let header = Header {
+        timestamp: 0,
+        l1_head: 0,
+        l1_finalized: None,
+        transactions_root: NMTRoot::new(block.transaction_nmt().commitment().digest()),
+    };
+    (header, block.get_namespace_proof(vm))
+}
+
+#[derive(Debug, Default)]
+struct L1Inner {
+    commitments: Vec<U256>,
+    events: Vec<Result<NewBlocks, L1Error>>,
+    verified_blocks: u64,
+    state_commitment: Option<U256>,
+    failing_commitment_reads: usize,
+    failing_verifications: usize,
+}
+
+/// An in-memory HotShot contract and rollup contract.
+#[derive(Clone, Debug, Default)]
+pub struct MockL1 {
+    inner: Arc<Mutex<L1Inner>>,
+}
+
+impl MockL1 {
+    /// Commit a batch of blocks to the HotShot contract, emitting a `NewBlocks` event.
+    pub fn commit_blocks(&self, headers: &[Header]) {
+        let mut inner = self.inner.lock().unwrap();
+        let first_block = inner.commitments.len() as u64;
+        inner.commitments.extend(
+            headers
+                .iter()
+                .map(|header| commitment_to_u256(header.commit())),
+        );
+        inner.events.push(Ok(NewBlocks {
+            first_block,
+            num_blocks: headers.len() as u64,
+        }));
+    }
+
+    /// Emit an error on the `NewBlocks` event stream.
+    pub fn inject_event_error(&self) {
+        self.inner
+            .lock()
+            .unwrap()
+            .events
+            .push(Err(L1Error::new("injected event error")));
+    }
+
+    /// Fail the next `count` reads of block commitments.
+    pub fn fail_commitment_reads(&self, count: usize) {
+        self.inner.lock().unwrap().failing_commitment_reads = count;
+    }
+
+    /// Fail the next `count` calls to `verify_blocks`.
+    pub fn fail_verifications(&self, count: usize) {
+        self.inner.lock().unwrap().failing_verifications = count;
+    }
+
+    /// The number of blocks verified by the rollup contract.
+    pub fn verified_blocks(&self) -> u64 {
+        self.inner.lock().unwrap().verified_blocks
+    }
+
+    /// The state commitment most recently verified by the rollup contract.
+    pub fn state_commitment(&self) -> Option<U256> {
+        self.inner.lock().unwrap().state_commitment
+    }
+}
+
+#[async_trait]
+impl RollupL1 for MockL1 {
+    async fn commitment(&self, height: u64) -> Result<U256, L1Error> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.failing_commitment_reads > 0 {
+            inner.failing_commitment_reads -= 1;
+            return Err(L1Error::new("injected commitment read error"));
+        }
+        // Like the contract, return zero for blocks which have not been committed
+        Ok(inner
+            .commitments
+            .get(height as usize)
+            .copied()
+            .unwrap_or_default())
+    }
+
+    async fn block_height(&self) -> Result<u64, L1Error> {
+        Ok(self.inner.lock().unwrap().commitments.len() as u64)
+    }
+
+    async fn new_blocks(&self) -> Result<BoxStream<'_, Result<NewBlocks, L1Error>>, L1Error> {
+        Ok(stream::unfold(0, move |index| async move {
+            loop {
+                let event = self.inner.lock().unwrap().events.get(index).cloned();
+                if let Some(event) = event {
+                    return Some((event, index + 1));
+                }
+                sleep(POLL_INTERVAL).await;
+            }
+        })
+        .boxed())
+    }
+
+    async fn verify_blocks(
+        &self,
+        num_blocks: u64,
+        state_comm: U256,
+        _proof: example_rollup::BatchProof,
+    ) -> Result<Option<U256>, L1Error> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.failing_verifications > 0 {
+            inner.failing_verifications -= 1;
+            return Err(L1Error::new("injected verification error"));
+        }
+        if inner.verified_blocks + num_blocks > inner.commitments.len() as u64 {
+            return Err(L1Error::new(format!(
+                "cannot verify {num_blocks} blocks, only {} are pending",
+                inner.commitments.len() as u64 - inner.verified_blocks
+            )));
+        }
+        inner.verified_blocks += num_blocks;
+        inner.state_commitment = Some(state_comm);
+        Ok(Some(U256::from(21000u64)))
+    }
+}
+
+#[derive(Debug, Default)]
+struct HotShotInner {
+    blocks: Vec<(Header, NamespaceProofType)>,
+    failing_proof_queries: usize,
+    failing_headers: usize,
+}
+
+/// An in-memory HotShot query service, serving the namespace of a single rollup.
+#[derive(Clone, Debug, Default)]
+pub struct MockHotShot {
+    inner: Arc<Mutex<HotShotInner>>,
+}
+
+impl MockHotShot {
+    /// Make the next block available, with its header and the rollup's namespace proof.
+    pub fn push_block(&self, header: Header, proof: NamespaceProofType) {
+        self.inner.lock().unwrap().blocks.push((header, proof));
+    }
+
+    /// Fail the next `count` namespace proof queries.
+    pub fn fail_proof_queries(&self, count: usize) {
+        self.inner.lock().unwrap().failing_proof_queries = count;
+    }
+
+    /// Yield an error instead of the next `count` headers on header streams.
+    pub fn fail_headers(&self, count: usize) {
+        self.inner.lock().unwrap().failing_headers = count;
+    }
+}
+
+#[async_trait]
+impl HotShotQuery for MockHotShot {
+    async fn headers(
+        &self,
+        from: u64,
+    ) -> Result<BoxStream<'_, Result<Header, QueryError>>, QueryError> {
+        Ok(stream::unfold(from as usize, move |height| async move {
+            loop {
+                {
+                    let mut inner = self.inner.lock().unwrap();
+                    if let Some((header, _)) = inner.blocks.get(height) {
+                        let header = header.clone();
+                        if inner.failing_headers > 0 {
+                            inner.failing_headers -= 1;
+                            return Some((Err(QueryError::new("injected header error")), height));
+                        }
+                        return Some((Ok(header), height + 1));
+                    }
+                }
+                sleep(POLL_INTERVAL).await;
+            }
+        })
+        .boxed())
+    }
+
+    async fn namespace_proof(
+        &self,
+        height: u64,
+        _vm_id: u64,
+    ) -> Result<NamespaceProofType, QueryError> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.failing_proof_queries > 0 {
+            inner.failing_proof_queries -= 1;
+            return Err(QueryError::new("injected namespace proof error"));
+        }
+        inner
+            .blocks
+            .get(height as usize)
+            .map(|(_, proof)| proof.clone())
+            .ok_or_else(|| QueryError::new(format!("block {height} is not available")))
+    }
+}
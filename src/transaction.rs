@@ -5,18 +5,155 @@
 // along with the sequencer-example-l2 repository. If not, see <https://mit-license.org/>.
 
 use crate::error::RollupError;
+use crate::multisig::{MultisigOp, MAX_OWNERS};
 use crate::state::{Amount, Nonce};
-use ethers::{abi::Address, signers::Signer, types::Signature};
+use ethers::{
+    abi::Address,
+    signers::Signer,
+    types::{transaction::eip2718::TypedTransaction, Bytes, Signature, H256, U256},
+    utils::{keccak256, rlp::Rlp},
+};
 use sequencer::VmTransaction;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+/// The maximum number of transfers in a single transaction, counting `destination` and each of
+/// the further `recipients` of a batch.
+pub const MAX_RECIPIENTS: usize = 64;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 /// Transaction struct represents a simple transaction in the rollup system.
 /// It contains the amount, destination address, and nonce for replay protection.
+/// An optional validity window, expressed in HotShot block heights, bounds when the
+/// transaction may be applied. A batch transfer pays further recipients under the same nonce.
+/// Transactions can also be sent from multisig accounts, and create or change multisig accounts.
 pub struct Transaction {
     pub amount: Amount,
     pub destination: Address,
     pub nonce: Nonce,
+    /// The transaction may only be applied in blocks after this height.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_after: Option<u64>,
+    /// The transaction may only be applied in blocks at or before this height.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_until: Option<u64>,
+    /// Further recipients of a batch transfer, paid along with `destination` under the same
+    /// signature and nonce. Either every recipient is paid, or none is.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<Recipient>,
+    /// The multisig account sending the transaction, which is signed by its owners. If `None`, the
+    /// sender is the signer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multisig: Option<Address>,
+    /// An operation on the owners of a multisig account, applied before the transfers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multisig_op: Option<MultisigOp>,
+}
+
+/// A recipient of a batch transfer.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Recipient {
+    pub destination: Address,
+    pub amount: Amount,
+}
+
+impl Transaction {
+    /// A batch transfer paying each of `recipients`, or `None` if there are no recipients.
+    pub fn batch(recipients: Vec<Recipient>, nonce: Nonce) -> Option<Self> {
+        let (first, rest) = recipients.split_first()?;
+        Some(Self {
+            amount: first.amount,
+            destination: first.destination,
+            nonce,
+            valid_after: None,
+            valid_until: None,
+            recipients: rest.to_vec(),
+            multisig: None,
+            multisig_op: None,
+        })
+    }
+
+    /// Every transfer made by the transaction: `destination` first, then any further recipients.
+    pub fn transfers(&self) -> impl Iterator<Item = Recipient> + '_ {
+        std::iter::once(Recipient {
+            destination: self.destination,
+            amount: self.amount,
+        })
+        .chain(self.recipients.iter().copied())
+    }
+
+    /// The total amount transferred, or `None` if it overflows.
+    pub fn total_amount(&self) -> Option<Amount> {
+        self.transfers().try_fold(0 as Amount, |total, transfer| {
+            total.checked_add(transfer.amount)
+        })
+    }
+
+    /// Check that the transaction makes at most `MAX_RECIPIENTS` transfers.
+    pub fn check_recipients(&self) -> Result<(), RollupError> {
+        let transfers = self.recipients.len() + 1;
+        if transfers > MAX_RECIPIENTS {
+            return Err(RollupError::TooManyRecipients {
+                max: MAX_RECIPIENTS,
+                actual: transfers,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns true if the transaction may be applied in a block at `height`.
+    pub fn is_valid_at(&self, height: u64) -> bool {
+        !self.valid_after.is_some_and(|after| height <= after)
+            && height <= self.valid_until.unwrap_or(u64::MAX)
+    }
+
+    /// Returns true if the transaction can no longer be applied in any block after `height`.
+    pub fn is_expired_after(&self, height: u64) -> bool {
+        self.valid_until.is_some_and(|until| until <= height)
+    }
+
+    /// Translates an Ethereum value transfer into a rollup transaction.
+    ///
+    /// Ethereum nonces count the transactions sent so far, starting from 0, while the rollup expects
+    /// the first transaction of an account to have nonce 1, so the nonce is shifted by one.
+    pub fn from_ethereum(transaction: &TypedTransaction) -> Result<Self, RollupError> {
+        let invalid = |reason: &str| RollupError::InvalidEthereumTransaction {
+            reason: reason.into(),
+        };
+        if transaction.data().is_some_and(|data| !data.is_empty()) {
+            return Err(invalid("only plain value transfers are supported"));
+        }
+        let destination = *transaction
+            .to()
+            .and_then(|to| to.as_address())
+            .ok_or_else(|| invalid("the recipient must be an address"))?;
+        let amount = transaction.value().copied().unwrap_or_default();
+        if amount > U256::from(Amount::MAX) {
+            return Err(invalid("the value does not fit in a rollup amount"));
+        }
+        let nonce = transaction.nonce().copied().unwrap_or_default();
+        if nonce >= U256::from(Nonce::MAX) {
+            return Err(invalid("the nonce does not fit in a rollup nonce"));
+        }
+        Ok(Self {
+            amount: amount.as_u64(),
+            destination,
+            nonce: nonce.as_u64() + 1,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
+        })
+    }
+}
+
+/// Decodes a signed Ethereum transaction from its RLP encoding.
+fn decode_ethereum_transaction(rlp: &Bytes) -> Result<(TypedTransaction, Signature), RollupError> {
+    TypedTransaction::decode_signed(&Rlp::new(rlp)).map_err(|err| {
+        RollupError::InvalidEthereumTransaction {
+            reason: err.to_string(),
+        }
+    })
 }
 
 impl VmTransaction for Transaction {
@@ -52,16 +189,126 @@ impl VmTransaction for SignedTransaction {
 pub struct SignedTransaction {
     pub transaction: Transaction,
     signature: Signature,
+    /// The RLP encoding of the signed Ethereum transaction that `transaction` was translated from.
+    /// When present, `signature` signs this Ethereum transaction instead of `transaction`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ethereum_rlp: Option<Bytes>,
+    /// Signatures of further owners of the multisig account sending the transaction.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cosignatures: Vec<Signature>,
 }
 
 impl SignedTransaction {
     /// Recovers the sender's address from the signature. Returns an error if the recovery fails.
+    ///
+    /// The sender of a multisig transaction is the multisig account, once all signatures have been
+    /// recovered. Whether the signers own the account is checked against the state.
     pub fn recover(&self) -> Result<Address, RollupError> {
+        match self.transaction.multisig {
+            Some(account) => {
+                self.signers()?;
+                Ok(account)
+            }
+            None => {
+                self.check_cosignatures()?;
+                self.recover_signer()
+            }
+        }
+    }
+
+    /// Recovers the address of every signer, starting with the signer of `signature`.
+    pub fn signers(&self) -> Result<Vec<Address>, RollupError> {
+        self.check_cosignatures()?;
+        let mut signers = vec![self.recover_signer()?];
         let bytes = self.transaction.encode();
-        self.signature
-            .recover(bytes)
+        for signature in &self.cosignatures {
+            signers.push(
+                signature
+                    .recover(bytes.clone())
+                    .map_err(|_| RollupError::SignatureError)?,
+            );
+        }
+        Ok(signers)
+    }
+
+    /// Check that only a multisig transaction carries cosignatures, and that there are at most
+    /// `MAX_OWNERS` signatures in total, so that recovery cost is bounded.
+    fn check_cosignatures(&self) -> Result<(), RollupError> {
+        if self.cosignatures.is_empty() {
+            return Ok(());
+        }
+        if self.transaction.multisig.is_none() || self.cosignatures.len() >= MAX_OWNERS {
+            return Err(RollupError::SignatureError);
+        }
+        Ok(())
+    }
+
+    /// Recovers the signer of `signature`.
+    fn recover_signer(&self) -> Result<Address, RollupError> {
+        let Some(rlp) = &self.ethereum_rlp else {
+            let bytes = self.transaction.encode();
+            return self
+                .signature
+                .recover(bytes)
+                .map_err(|_| RollupError::SignatureError);
+        };
+        // The signature only authenticates the Ethereum transaction, so check that the rollup
+        // transaction is exactly its translation.
+        let (ethereum_transaction, signature) = decode_ethereum_transaction(rlp)?;
+        if signature != self.signature
+            || Transaction::from_ethereum(&ethereum_transaction)? != self.transaction
+        {
+            return Err(RollupError::SignatureError);
+        }
+        signature
+            .recover(ethereum_transaction.sighash())
             .map_err(|_| RollupError::SignatureError)
     }
+
+    /// Translates a signed Ethereum value transfer, as sent by ordinary Ethereum wallets, into a
+    /// rollup transaction. The original signature is kept, so no re-signing is needed.
+    pub fn from_ethereum_rlp(rlp: Bytes) -> Result<Self, RollupError> {
+        let (ethereum_transaction, signature) = decode_ethereum_transaction(&rlp)?;
+        Ok(Self {
+            transaction: Transaction::from_ethereum(&ethereum_transaction)?,
+            signature,
+            ethereum_rlp: Some(rlp),
+            cosignatures: vec![],
+        })
+    }
+
+    /// The hash of the Ethereum transaction this transaction was translated from, if any, as known
+    /// to Ethereum wallets.
+    pub fn ethereum_hash(&self) -> Option<H256> {
+        self.ethereum_rlp
+            .as_ref()
+            .map(|rlp| H256::from(keccak256(rlp)))
+    }
+
+    /// The chain ID of the Ethereum transaction this transaction was translated from, if any.
+    pub fn ethereum_chain_id(&self) -> Option<u64> {
+        let rlp = self.ethereum_rlp.as_ref()?;
+        let (ethereum_transaction, _) = decode_ethereum_transaction(rlp).ok()?;
+        ethereum_transaction.chain_id().map(|id| id.as_u64())
+    }
+
+    /// Check that a transaction translated from Ethereum was signed for `chain_id`, the rollup's
+    /// chain ID. Transfers signed for another chain, or legacy transfers signed without a chain ID, would
+    /// otherwise be replayable on the rollup.
+    pub fn check_chain_id(&self, chain_id: u64) -> Result<(), RollupError> {
+        if self.ethereum_rlp.is_none() {
+            return Ok(());
+        }
+        let actual = self.ethereum_chain_id();
+        if actual != Some(chain_id) {
+            return Err(RollupError::WrongChainId {
+                expected: chain_id,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
     /// Creates a new SignedTransaction by signing the transaction data with the given wallet.
     /// This is asynchronous as it involves cryptographic signing.
     pub async fn new(transaction: Transaction, wallet: &impl Signer) -> Self {
@@ -70,8 +317,17 @@ impl SignedTransaction {
         Self {
             signature,
             transaction,
+            ethereum_rlp: None,
+            cosignatures: vec![],
         }
     }
+
+    /// Adds the signature of a further owner of the multisig account sending the transaction.
+    pub async fn cosign(&mut self, wallet: &impl Signer) {
+        let bytes = self.transaction.encode();
+        self.cosignatures
+            .push(wallet.sign_message(&bytes).await.unwrap());
+    }
 }
 
 #[cfg(test)]
@@ -88,6 +344,11 @@ mod tests {
             amount: 100,
             destination: alice.address(),
             nonce: 1,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
         };
         let signed_transaction = SignedTransaction::new(transaction, &alice).await;
         let recovered_address = signed_transaction
@@ -95,4 +356,149 @@ mod tests {
             .expect("Should recover address");
         assert_eq!(recovered_address, alice.address());
     }
+
+    #[async_std::test]
+    async fn test_ethereum_transaction() {
+        let mut rng = rand::thread_rng();
+        let alice = LocalWallet::new(&mut rng).with_chain_id(1u64);
+        let bob = LocalWallet::new(&mut rng);
+        let request: TypedTransaction = ethers::types::TransactionRequest::new()
+            .to(bob.address())
+            .value(100)
+            .nonce(0)
+            .gas(21000)
+            .gas_price(0)
+            .chain_id(1)
+            .into();
+        let signature = alice.sign_transaction(&request).await.unwrap();
+        let rlp = request.rlp_signed(&signature);
+
+        let signed_transaction =
+            SignedTransaction::from_ethereum_rlp(rlp).expect("Should translate transfer");
+        assert_eq!(
+            signed_transaction.transaction,
+            Transaction {
+                amount: 100,
+                destination: bob.address(),
+                nonce: 1,
+                valid_after: None,
+                valid_until: None,
+                recipients: vec![],
+                multisig: None,
+                multisig_op: None,
+            }
+        );
+        assert_eq!(signed_transaction.ethereum_chain_id(), Some(1));
+        assert_eq!(
+            signed_transaction.ethereum_hash(),
+            Some(request.hash(&signature))
+        );
+        assert_eq!(signed_transaction.recover().unwrap(), alice.address());
+
+        // Survives the round trip through the namespace encoding
+        let decoded = SignedTransaction::decode(&signed_transaction.encode()).unwrap();
+        assert_eq!(decoded.recover().unwrap(), alice.address());
+
+        // Tampering with the translated transaction invalidates the signature
+        let mut tampered = signed_transaction;
+        tampered.transaction.amount = 1000;
+        assert_eq!(tampered.recover(), Err(RollupError::SignatureError));
+    }
+
+    #[async_std::test]
+    async fn test_multisig_signatures() {
+        let mut rng = rand::thread_rng();
+        let alice = LocalWallet::new(&mut rng);
+        let bob = LocalWallet::new(&mut rng);
+        let account = Address::repeat_byte(1);
+        let transaction = Transaction {
+            amount: 100,
+            destination: alice.address(),
+            nonce: 1,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: Some(account),
+            multisig_op: None,
+        };
+        let mut signed_transaction = SignedTransaction::new(transaction, &alice).await;
+        signed_transaction.cosign(&bob).await;
+
+        // The sender is the multisig account, signed for by both owners
+        let decoded = SignedTransaction::decode(&signed_transaction.encode()).unwrap();
+        assert_eq!(decoded.recover(), Ok(account));
+        assert_eq!(decoded.signers(), Ok(vec![alice.address(), bob.address()]));
+
+        // Signatures do not carry over to a different account
+        let mut tampered = decoded;
+        tampered.transaction.multisig = Some(Address::repeat_byte(2));
+        assert_ne!(tampered.signers(), Ok(vec![alice.address(), bob.address()]));
+
+        // Cosignatures are only accepted on multisig transactions
+        let mut transfer = SignedTransaction::new(
+            Transaction {
+                multisig: None,
+                ..signed_transaction.transaction.clone()
+            },
+            &alice,
+        )
+        .await;
+        assert_eq!(transfer.recover(), Ok(alice.address()));
+        transfer.cosign(&bob).await;
+        assert_eq!(transfer.recover(), Err(RollupError::SignatureError));
+        assert_eq!(transfer.signers(), Err(RollupError::SignatureError));
+
+        // At most `MAX_OWNERS` signatures are checked
+        for _ in 2..MAX_OWNERS {
+            signed_transaction.cosign(&bob).await;
+        }
+        assert_eq!(signed_transaction.signers().unwrap().len(), MAX_OWNERS);
+        signed_transaction.cosign(&bob).await;
+        assert_eq!(
+            signed_transaction.recover(),
+            Err(RollupError::SignatureError)
+        );
+        assert_eq!(
+            signed_transaction.signers(),
+            Err(RollupError::SignatureError)
+        );
+    }
+
+    #[test]
+    fn test_validity_window() {
+        let mut transaction = Transaction {
+            amount: 100,
+            destination: Address::zero(),
+            nonce: 1,
+            valid_after: None,
+            valid_until: None,
+            recipients: vec![],
+            multisig: None,
+            multisig_op: None,
+        };
+        assert!(transaction.is_valid_at(0));
+        assert!(!transaction.is_expired_after(u64::MAX));
+
+        transaction.valid_after = Some(5);
+        transaction.valid_until = Some(10);
+        assert!(!transaction.is_valid_at(4));
+        // `valid_after` is exclusive, `valid_until` is inclusive
+        assert!(!transaction.is_valid_at(5));
+        assert!(transaction.is_valid_at(6));
+        assert!(transaction.is_valid_at(10));
+        assert!(!transaction.is_valid_at(11));
+        assert!(!transaction.is_expired_after(9));
+        assert!(transaction.is_expired_after(10));
+
+        // Transactions without a window keep their original encoding.
+        transaction.valid_after = None;
+        transaction.valid_until = None;
+        assert_eq!(
+            String::from_utf8(transaction.encode()).unwrap(),
+            format!(
+                "{{\"amount\":100,\"destination\":\"{:?}\",\"nonce\":1}}",
+                Address::zero()
+            )
+        );
+    }
 }